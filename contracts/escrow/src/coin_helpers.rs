@@ -0,0 +1,173 @@
+use crate::error::ContractError;
+use cosmwasm_std::{Coin, Decimal};
+use cw20::Cw20CoinVerified;
+
+/// add_coins merges the coins into the balance, combining amounts of the same denom
+pub fn add_coins(balance: &mut Vec<Coin>, coins: &[Coin]) {
+    for coin in coins {
+        match balance.iter_mut().find(|c| c.denom == coin.denom) {
+            Some(existing) => existing.amount += coin.amount,
+            None => balance.push(coin.clone()),
+        }
+    }
+}
+
+/// sub_coins removes the coins from the balance, dropping denoms that reach zero
+pub fn sub_coins(balance: &mut Vec<Coin>, coins: &[Coin]) -> Result<(), ContractError> {
+    for coin in coins {
+        let existing = balance
+            .iter_mut()
+            .find(|c| c.denom == coin.denom)
+            .ok_or_else(|| ContractError::InsufficientBalance {
+                token: coin.denom.clone(),
+            })?;
+        existing.amount = existing.amount.checked_sub(coin.amount).map_err(|_| {
+            ContractError::InsufficientBalance {
+                token: coin.denom.clone(),
+            }
+        })?;
+    }
+    balance.retain(|c| !c.amount.is_zero());
+    Ok(())
+}
+
+/// add_cw20 merges the cw20 tokens into the balance, combining amounts of the same contract
+pub fn add_cw20(balance: &mut Vec<Cw20CoinVerified>, tokens: &[Cw20CoinVerified]) {
+    for token in tokens {
+        match balance.iter_mut().find(|c| c.address == token.address) {
+            Some(existing) => existing.amount += token.amount,
+            None => balance.push(token.clone()),
+        }
+    }
+}
+
+/// sub_cw20 removes the cw20 tokens from the balance, dropping contracts that reach zero
+pub fn sub_cw20(
+    balance: &mut Vec<Cw20CoinVerified>,
+    tokens: &[Cw20CoinVerified],
+) -> Result<(), ContractError> {
+    for token in tokens {
+        let insufficient = || ContractError::InsufficientBalance {
+            token: token.address.to_string(),
+        };
+        let existing = balance
+            .iter_mut()
+            .find(|c| c.address == token.address)
+            .ok_or_else(insufficient)?;
+        existing.amount = existing
+            .amount
+            .checked_sub(token.amount)
+            .map_err(|_| insufficient())?;
+    }
+    balance.retain(|c| !c.is_empty());
+    Ok(())
+}
+
+/// split_coins divides every coin by share, returning (share, remainder).
+/// The share is rounded down, so the remainder receives any leftover dust.
+pub fn split_coins(coins: &[Coin], share: Decimal) -> (Vec<Coin>, Vec<Coin>) {
+    let mut shares = vec![];
+    let mut remainders = vec![];
+    for coin in coins {
+        let part = coin.amount * share;
+        shares.push(Coin::new(part.u128(), &coin.denom));
+        remainders.push(Coin::new((coin.amount - part).u128(), &coin.denom));
+    }
+    shares.retain(|c| !c.amount.is_zero());
+    remainders.retain(|c| !c.amount.is_zero());
+    (shares, remainders)
+}
+
+/// split_cw20 divides every cw20 token by share, returning (share, remainder).
+/// The share is rounded down, so the remainder receives any leftover dust.
+pub fn split_cw20(
+    tokens: &[Cw20CoinVerified],
+    share: Decimal,
+) -> (Vec<Cw20CoinVerified>, Vec<Cw20CoinVerified>) {
+    let mut shares = vec![];
+    let mut remainders = vec![];
+    for token in tokens {
+        let part = token.amount * share;
+        shares.push(Cw20CoinVerified {
+            address: token.address.clone(),
+            amount: part,
+        });
+        remainders.push(Cw20CoinVerified {
+            address: token.address.clone(),
+            amount: token.amount - part,
+        });
+    }
+    shares.retain(|c| !c.is_empty());
+    remainders.retain(|c| !c.is_empty());
+    (shares, remainders)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cosmwasm_std::{coin, Addr, Uint128};
+
+    #[test]
+    fn add_and_sub_coins_works() {
+        let mut balance = vec![coin(10, "earth")];
+        add_coins(&mut balance, &[coin(5, "earth"), coin(3, "moon")]);
+        assert_eq!(balance, vec![coin(15, "earth"), coin(3, "moon")]);
+
+        sub_coins(&mut balance, &[coin(3, "moon"), coin(5, "earth")]).unwrap();
+        assert_eq!(balance, vec![coin(10, "earth")]);
+
+        match sub_coins(&mut balance, &[coin(11, "earth")]) {
+            Ok(()) => panic!("Should have raised insufficient balance error"),
+            Err(ContractError::InsufficientBalance { token }) => assert_eq!(token, "earth"),
+            Err(e) => panic!("Unexpected error: {:?}", e),
+        }
+
+        match sub_coins(&mut balance, &[coin(1, "moon")]) {
+            Ok(()) => panic!("Should have raised insufficient balance error"),
+            Err(ContractError::InsufficientBalance { token }) => assert_eq!(token, "moon"),
+            Err(e) => panic!("Unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn split_coins_works() {
+        let coins = vec![coin(10, "earth"), coin(1, "moon")];
+        let (share, rest) = split_coins(&coins, Decimal::percent(25));
+        assert_eq!(share, vec![coin(2, "earth")]);
+        assert_eq!(rest, vec![coin(8, "earth"), coin(1, "moon")]);
+
+        let (share, rest) = split_coins(&coins, Decimal::one());
+        assert_eq!(share, coins);
+        assert_eq!(rest, vec![]);
+
+        let tokens = vec![Cw20CoinVerified {
+            address: Addr::unchecked("foo"),
+            amount: Uint128::new(7),
+        }];
+        let (share, rest) = split_cw20(&tokens, Decimal::percent(50));
+        assert_eq!(share[0].amount, Uint128::new(3));
+        assert_eq!(rest[0].amount, Uint128::new(4));
+    }
+
+    #[test]
+    fn add_and_sub_cw20_works() {
+        let token = |address: &str, amount: u128| Cw20CoinVerified {
+            address: Addr::unchecked(address),
+            amount: Uint128::new(amount),
+        };
+
+        let mut balance = vec![];
+        add_cw20(&mut balance, &[token("foo", 10), token("bar", 2)]);
+        add_cw20(&mut balance, &[token("foo", 5)]);
+        assert_eq!(balance, vec![token("foo", 15), token("bar", 2)]);
+
+        sub_cw20(&mut balance, &[token("bar", 2)]).unwrap();
+        assert_eq!(balance, vec![token("foo", 15)]);
+
+        match sub_cw20(&mut balance, &[token("foo", 16)]) {
+            Ok(()) => panic!("Should have raised insufficient balance error"),
+            Err(ContractError::InsufficientBalance { token }) => assert_eq!(token, "foo"),
+            Err(e) => panic!("Unexpected error: {:?}", e),
+        }
+    }
+}
@@ -1,16 +1,36 @@
 use cosmwasm_std::{
-    entry_point, to_binary, Addr, BankMsg, Binary, Coin, Deps, DepsMut, Env, MessageInfo, Response,
-    StdResult,
+    entry_point, from_binary, to_binary, to_vec, Addr, BankMsg, Binary, Coin, ContractResult,
+    CosmosMsg, Decimal, Deps, DepsMut, Empty, Env, MessageInfo, Order, QueryRequest, Response,
+    StdError, StdResult, Storage, SystemResult, Timestamp, Uint128, WasmQuery,
 };
+use cw_storage_plus::Bound;
 
+use crate::coin_helpers::{add_coins, add_cw20, split_coins, split_cw20, sub_coins, sub_cw20};
 use crate::error::ContractError;
-use crate::msg::{ArbiterResponse, ExecuteMsg, InstantiateMsg, QueryMsg};
-use crate::state::{Config, CONFIG};
-use cw2::set_contract_version;
+use crate::msg::{
+    ArbiterMsg, ArbiterProposalResponse, ArbiterResponse, DepositInfo, DepositsResponse,
+    DetailsResponse, DisputeResponse, ExecuteMsg, FeeResponse, HashlockResponse, InstantiateMsg,
+    ListResponse, MigrateMsg, MilestoneMsg, MilestoneResponse, MilestoneStatus, MilestonesResponse,
+    PendingReleasesResponse, QueryMsg, ReceiveMsg, ReleaseConditionResponse, VestingMsg,
+    VestingResponse,
+};
+use crate::state::{
+    escrows, Arbiter, ArbiterProposal, Config, Dispute, Escrow, Fee, Hashlock, Milestone,
+    PendingRelease, Release, ReleaseCondition, Vesting, ARBITER_PROPOSAL, CONFIG, CW20_BALANCES,
+    DEPOSITS, DISPUTE, FEE, FEES_COLLECTED, HASHLOCK, MILESTONES, PENDING_RELEASES,
+    REGISTRY_NATIVE_BALANCE, RELEASE_CONDITION, STORED_CONFIG, VESTING,
+};
+use cw2::{get_contract_version, set_contract_version};
+use cw20::{Cw20Coin, Cw20CoinVerified, Cw20Contract, Cw20ExecuteMsg, Cw20ReceiveMsg};
+use cw_utils::Expiration;
+use semver::Version;
+use sha2::{Digest, Sha256};
 
 // Version info, for migration info
-const CONTRACT_NAME: &str = "crates.io:cw20-merkle-airdrop";
+const CONTRACT_NAME: &str = "crates.io:cw-escrow";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
+// name recorded by versions up to 0.11
+const LEGACY_CONTRACT_NAME: &str = "crates.io:cw20-merkle-airdrop";
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
@@ -19,11 +39,38 @@ pub fn instantiate(
     info: MessageInfo,
     msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
+    let arbiter = deps.api.addr_validate(&msg.arbiter)?;
+    let arbiters = match msg.arbiters {
+        Some(arbiters) => {
+            let arbiters = validate_arbiters(deps.as_ref(), arbiters)?;
+            // a handover of `arbiter` passes its weight on, so it must have one
+            if !arbiters.iter().any(|a| a.addr == arbiter) {
+                return Err(ContractError::ArbiterNotInArbiters {});
+            }
+            arbiters
+        }
+        None => vec![Arbiter {
+            addr: arbiter.clone(),
+            weight: 1,
+        }],
+    };
+    let total_weight = arbiters.iter().map(|arbiter| arbiter.weight).sum();
+    let threshold = msg.threshold.unwrap_or(total_weight);
+    if threshold == 0 || threshold > total_weight {
+        return Err(ContractError::InvalidThreshold {
+            threshold,
+            total_weight,
+        });
+    }
+
     let config = Config {
-        arbiter: deps.api.addr_validate(&msg.arbiter)?,
+        arbiter,
         recipient: deps.api.addr_validate(&msg.recipient)?,
-        source: info.sender,
+        source: info.sender.clone(),
         expiration: msg.expiration,
+        cw20_whitelist: validate_whitelist(deps.as_ref(), msg.cw20_whitelist)?,
+        arbiters,
+        threshold,
     };
 
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
@@ -34,9 +81,163 @@ pub fn instantiate(
         }
     }
     CONFIG.save(deps.storage, &config)?;
+    if let Some(fee) = msg.fee {
+        if fee.rate >= Decimal::one() {
+            return Err(ContractError::InvalidFeeRate { rate: fee.rate });
+        }
+        let fee = Fee {
+            collector: deps.api.addr_validate(&fee.collector)?,
+            rate: fee.rate,
+        };
+        FEE.save(deps.storage, &fee)?;
+    }
+    if let Some(hash) = msg.hashlock {
+        // the hashlock needs the refund path after expiration
+        if msg.expiration.is_none()
+            || msg.milestones.is_some()
+            || msg.vesting.is_some()
+            || msg.release_condition.is_some()
+        {
+            return Err(ContractError::InvalidHashlock {});
+        }
+        let hash = hex::decode(&hash).map_err(|_| ContractError::InvalidHashlock {})?;
+        if hash.len() != 32 {
+            return Err(ContractError::InvalidHashlock {});
+        }
+        let hashlock = Hashlock {
+            hash: hex::encode(hash),
+            preimage: None,
+        };
+        HASHLOCK.save(deps.storage, &hashlock)?;
+    }
+    if let Some(condition) = msg.release_condition {
+        if msg.vesting.is_some() || msg.milestones.is_some() {
+            return Err(ContractError::InvalidReleaseCondition {});
+        }
+        let condition = ReleaseCondition {
+            contract: deps.api.addr_validate(&condition.contract)?,
+            query: condition.query,
+            expected: condition.expected,
+        };
+        RELEASE_CONDITION.save(deps.storage, &condition)?;
+    }
+    if let Some(vesting) = msg.vesting {
+        if msg.expiration.is_some() || msg.milestones.is_some() {
+            return Err(ContractError::InvalidVesting {});
+        }
+        save_vesting(deps.storage, &env, vesting)?;
+    }
+    if let Some(milestones) = msg.milestones {
+        // Refund is the only way out for the funds of milestones that are never approved
+        if msg.expiration.is_none() {
+            return Err(ContractError::MilestonesWithoutExpiration {});
+        }
+        save_milestones(deps.storage, &env, milestones)?;
+    }
+    if !info.funds.is_empty() {
+        DEPOSITS.save(deps.storage, &info.sender, &info.funds)?;
+    }
     Ok(Response::default())
 }
 
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+    let stored = get_contract_version(deps.storage)?;
+    if stored.contract != CONTRACT_NAME && stored.contract != LEGACY_CONTRACT_NAME {
+        return Err(ContractError::CannotMigrate {
+            contract: stored.contract,
+        });
+    }
+    let version: Version = CONTRACT_VERSION.parse()?;
+    if stored.version.parse::<Version>()? > version {
+        return Err(ContractError::CannotMigrateVersion {
+            version: stored.version,
+        });
+    }
+
+    // rewrite the config with the fields missing from older layouts
+    let config: Config = STORED_CONFIG.load(deps.storage)?.into();
+    CONFIG.save(deps.storage, &config)?;
+
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    Ok(Response::new()
+        .add_attribute("action", "migrate")
+        .add_attribute("from_version", stored.version)
+        .add_attribute("to_version", CONTRACT_VERSION))
+}
+
+fn save_milestones(
+    storage: &mut dyn Storage,
+    env: &Env,
+    milestones: Vec<MilestoneMsg>,
+) -> Result<(), ContractError> {
+    if milestones.is_empty() {
+        return Err(ContractError::NoMilestones {});
+    }
+    let milestones = milestones
+        .into_iter()
+        .enumerate()
+        .map(|(index, milestone)| {
+            if is_expired(milestone.deadline, env) {
+                return Err(ContractError::MilestoneExpired {
+                    index: index as u32,
+                });
+            }
+            Ok(Milestone {
+                title: milestone.title,
+                amount: milestone.amount,
+                deadline: milestone.deadline,
+                released_at: None,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(MILESTONES.save(storage, &milestones)?)
+}
+
+fn save_vesting(
+    storage: &mut dyn Storage,
+    env: &Env,
+    vesting: VestingMsg,
+) -> Result<(), ContractError> {
+    let start = vesting.start.unwrap_or(env.block.time);
+    let cliff = vesting.cliff.unwrap_or(start);
+    if start > cliff || cliff > vesting.end || start >= vesting.end {
+        return Err(ContractError::InvalidVesting {});
+    }
+    let vesting = Vesting {
+        amount: vesting.amount,
+        start,
+        cliff: vesting.cliff,
+        end: vesting.end,
+        claimed: vec![],
+        cancelled_at: None,
+    };
+    Ok(VESTING.save(storage, &vesting)?)
+}
+
+fn validate_arbiters(deps: Deps, arbiters: Vec<ArbiterMsg>) -> Result<Vec<Arbiter>, ContractError> {
+    let mut validated: Vec<Arbiter> = Vec::with_capacity(arbiters.len());
+    for arbiter in arbiters {
+        let addr = deps.api.addr_validate(&arbiter.addr)?;
+        if arbiter.weight == 0 || validated.iter().any(|a| a.addr == addr) {
+            return Err(ContractError::InvalidArbiters {});
+        }
+        validated.push(Arbiter {
+            addr,
+            weight: arbiter.weight,
+        });
+    }
+    Ok(validated)
+}
+
+fn validate_whitelist(deps: Deps, whitelist: Option<Vec<String>>) -> StdResult<Vec<Addr>> {
+    whitelist
+        .unwrap_or_default()
+        .iter()
+        .map(|addr| deps.api.addr_validate(addr))
+        .collect()
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn execute(
     deps: DepsMut,
@@ -45,21 +246,183 @@ pub fn execute(
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
     match msg {
-        ExecuteMsg::Approve { quantity } => execute_approve(deps, env, info, quantity),
-        ExecuteMsg::Refund {} => execute_refund(deps, env, info),
+        ExecuteMsg::Approve {
+            id: None,
+            quantity,
+            cw20_quantity,
+        } => execute_approve(deps, env, info, quantity, cw20_quantity),
+        ExecuteMsg::Approve {
+            id: Some(id),
+            quantity,
+            cw20_quantity,
+        } => execute_approve_escrow(deps, env, info, id, quantity, cw20_quantity),
+        ExecuteMsg::Refund { id: None } => execute_refund(deps, env, info),
+        ExecuteMsg::Refund { id: Some(id) } => execute_refund_escrow(deps, env, info, id),
+        ExecuteMsg::Receive(msg) => execute_receive(deps, info, msg),
+        ExecuteMsg::Create {
+            id,
+            arbiter,
+            recipient,
+            expiration,
+            cw20_whitelist,
+        } => execute_create(
+            deps,
+            env,
+            info,
+            id,
+            arbiter,
+            recipient,
+            expiration,
+            cw20_whitelist,
+        ),
+        ExecuteMsg::Deposit { id } => execute_deposit(deps, info, id),
+        ExecuteMsg::TopUp {} => execute_top_up(deps, env, info),
+        ExecuteMsg::ApproveMilestone { index } => execute_approve_milestone(deps, env, info, index),
+        ExecuteMsg::Dispute { reason } => execute_dispute(deps, env, info, reason),
+        ExecuteMsg::Resolve { recipient_share } => {
+            execute_resolve(deps, env, info, recipient_share)
+        }
+        ExecuteMsg::Claim { preimage } => execute_claim(deps, env, info, preimage),
+        ExecuteMsg::CancelVesting {} => execute_cancel_vesting(deps, env, info),
+        ExecuteMsg::UpdateArbiter { arbiter } => execute_update_arbiter(deps, info, arbiter),
+        ExecuteMsg::AcceptArbiter { arbiter } => execute_accept_arbiter(deps, info, arbiter),
+        ExecuteMsg::UpdateRecipient { recipient } => {
+            execute_update_recipient(deps, info, recipient)
+        }
+        ExecuteMsg::ReleaseIfConditionMet {} => execute_release_if_condition_met(deps, env),
     }
 }
 
-fn execute_approve(
+fn execute_top_up(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
+    if info.funds.is_empty() {
+        return Err(ContractError::NoFunds {});
+    }
+
+    assert_not_disputed(deps.storage)?;
+
+    // once expired, the escrow can only be refunded to the source
+    let config = CONFIG.load(deps.storage)?;
+    if let Some(expiration) = config.expiration {
+        if expiration.is_expired(&env.block) {
+            return Err(ContractError::Expired { expiration });
+        }
+    }
+
+    DEPOSITS.update(deps.storage, &info.sender, |deposit| -> StdResult<_> {
+        let mut deposit = deposit.unwrap_or_default();
+        add_coins(&mut deposit, &info.funds);
+        Ok(deposit)
+    })?;
+
+    Ok(Response::new()
+        .add_attribute("action", "top_up")
+        .add_attribute("depositor", info.sender))
+}
+
+fn execute_receive(
+    deps: DepsMut,
+    info: MessageInfo,
+    wrapped: Cw20ReceiveMsg,
+) -> Result<Response, ContractError> {
+    let msg: ReceiveMsg = from_binary(&wrapped.msg)?;
+    let token = Cw20CoinVerified {
+        address: info.sender,
+        amount: wrapped.amount,
+    };
+    match msg {
+        ReceiveMsg::Deposit { id: None } => {
+            // only whitelisted cw20 contracts can deposit
+            let config = CONFIG.load(deps.storage)?;
+            if !config.cw20_whitelist.contains(&token.address) {
+                return Err(ContractError::NotInWhitelist {
+                    token: token.address.into(),
+                });
+            }
+            CW20_BALANCES.update(deps.storage, &token.address, |balance| -> StdResult<_> {
+                Ok(balance.unwrap_or_default().checked_add(token.amount)?)
+            })?;
+        }
+        ReceiveMsg::Deposit { id: Some(ref id) } => {
+            let mut escrow = escrows().load(deps.storage, id)?;
+            if !escrow.cw20_whitelist.contains(&token.address) {
+                return Err(ContractError::NotInWhitelist {
+                    token: token.address.into(),
+                });
+            }
+            add_cw20(&mut escrow.cw20_balance, std::slice::from_ref(&token));
+            escrows().save(deps.storage, id, &escrow)?;
+        }
+    }
+    Ok(Response::new()
+        .add_attribute("action", "deposit")
+        .add_attribute("token", token.address)
+        .add_attribute("amount", token.amount)
+        .add_attribute("sender", wrapped.sender))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn execute_create(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: String,
+    arbiter: String,
+    recipient: String,
+    expiration: Option<Expiration>,
+    cw20_whitelist: Option<Vec<String>>,
+) -> Result<Response, ContractError> {
+    if let Some(expiration) = expiration {
+        if expiration.is_expired(&env.block) {
+            return Err(ContractError::Expired { expiration });
+        }
+    }
+
+    let escrow = Escrow {
+        arbiter: deps.api.addr_validate(&arbiter)?,
+        recipient: deps.api.addr_validate(&recipient)?,
+        source: info.sender,
+        expiration,
+        cw20_whitelist: validate_whitelist(deps.as_ref(), cw20_whitelist)?,
+        native_balance: info.funds,
+        cw20_balance: vec![],
+    };
+
+    escrows().update(deps.storage, &id, |existing| match existing {
+        None => Ok(escrow.clone()),
+        Some(_) => Err(ContractError::AlreadyInUse { id: id.clone() }),
+    })?;
+    track_registry_deposit(deps.storage, &escrow.native_balance)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "create")
+        .add_attribute("id", id))
+}
+
+fn execute_deposit(
     deps: DepsMut,
+    info: MessageInfo,
+    id: String,
+) -> Result<Response, ContractError> {
+    let mut escrow = escrows().load(deps.storage, &id)?;
+    add_coins(&mut escrow.native_balance, &info.funds);
+    escrows().save(deps.storage, &id, &escrow)?;
+    track_registry_deposit(deps.storage, &info.funds)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "deposit")
+        .add_attribute("id", id))
+}
+
+fn execute_approve(
+    mut deps: DepsMut,
     env: Env,
     info: MessageInfo,
     quantity: Option<Vec<Coin>>,
+    cw20_quantity: Option<Vec<Cw20Coin>>,
 ) -> Result<Response, ContractError> {
     let config = CONFIG.load(deps.storage)?;
-    if info.sender != config.arbiter {
-        return Err(ContractError::Unauthorized {});
-    }
+    let weight = arbiter_weight(&config, &info.sender)?;
+    assert_not_disputed(deps.storage)?;
 
     // throws error if the contract is expired
     if let Some(expiration) = config.expiration {
@@ -68,162 +431,1227 @@ fn execute_approve(
         }
     }
 
-    let amount = if let Some(quantity) = quantity {
-        quantity
-    } else {
+    // milestone escrows only release funds through ApproveMilestone
+    if MILESTONES.may_load(deps.storage)?.is_some() {
+        return Err(ContractError::MilestonesDefined {});
+    }
+    // vesting escrows only release funds through Claim
+    if VESTING.may_load(deps.storage)?.is_some() {
+        return Err(ContractError::VestingDefined {});
+    }
+    // and so do hash-time-locked ones
+    if HASHLOCK.may_load(deps.storage)?.is_some() {
+        return Err(ContractError::HashlockDefined {});
+    }
+
+    let release = Release::Approve {
+        recipient: config.recipient.clone(),
+        quantity: quantity.clone(),
+        cw20_quantity: cw20_quantity.clone(),
+    };
+    if !vote(deps.storage, &config, &info.sender, weight, release)? {
+        return Ok(pending_response(&info.sender, "approve"));
+    }
+
+    let held = native_balance(deps.as_ref(), &env)?;
+    let (amount, cw20_amount) = if quantity.is_none() && cw20_quantity.is_none() {
         // release everything
-        // Querier guarantees to return up-to-date data, including funds sent in this handle message
-        // https://github.com/CosmWasm/wasmd/blob/master/x/wasm/internal/keeper/keeper.go#L185-L192
-        deps.querier.query_all_balances(&env.contract.address)?
+        (held.clone(), cw20_balances(deps.as_ref())?)
+    } else {
+        let quantity = quantity.unwrap_or_default();
+        // coins held for registry escrows cannot be released from here
+        sub_coins(&mut held.clone(), &quantity)?;
+        (quantity, validate_cw20(deps.as_ref(), cw20_quantity)?)
     };
-    Ok(send_tokens(config.recipient, amount, "approve"))
+    reduce_deposits(deps.storage, &held, &amount)?;
+    release_cw20(deps.branch(), &cw20_amount)?;
+    release_tokens(
+        deps.storage,
+        config.recipient,
+        amount,
+        cw20_amount,
+        "approve",
+    )
 }
 
-fn execute_refund(deps: DepsMut, env: Env, _info: MessageInfo) -> Result<Response, ContractError> {
+fn execute_release_if_condition_met(
+    mut deps: DepsMut,
+    env: Env,
+) -> Result<Response, ContractError> {
     let config = CONFIG.load(deps.storage)?;
-    // anyone can try to refund, as long as the contract is expired
+    assert_not_disputed(deps.storage)?;
+
+    // throws error if the contract is expired
     if let Some(expiration) = config.expiration {
-        if !expiration.is_expired(&env.block) {
-            return Err(ContractError::NotExpired {});
+        if expiration.is_expired(&env.block) {
+            return Err(ContractError::Expired { expiration });
         }
-    } else {
-        return Err(ContractError::NotExpired {});
     }
 
-    // Querier guarantees to return up-to-date data, including funds sent in this handle message
-    // https://github.com/CosmWasm/wasmd/blob/master/x/wasm/internal/keeper/keeper.go#L185-L192
-    let balance = deps.querier.query_all_balances(&env.contract.address)?;
-    Ok(send_tokens(config.source, balance, "refund"))
-}
+    let condition = RELEASE_CONDITION
+        .may_load(deps.storage)?
+        .ok_or(ContractError::NoReleaseCondition {})?;
+    if !is_condition_met(deps.as_ref(), &condition)? {
+        return Err(ContractError::ConditionNotMet {});
+    }
 
-// this is a helper to move the tokens, so the business logic is easy to read
-fn send_tokens(to_address: Addr, amount: Vec<Coin>, action: &str) -> Response {
-    Response::new()
-        .add_message(BankMsg::Send {
-            to_address: to_address.clone().into(),
-            amount,
-        })
-        .add_attribute("action", action)
-        .add_attribute("to", to_address)
+    let amount = native_balance(deps.as_ref(), &env)?;
+    let cw20_amount = cw20_balances(deps.as_ref())?;
+    reduce_deposits(deps.storage, &amount, &amount)?;
+    release_cw20(deps.branch(), &cw20_amount)?;
+    release_tokens(
+        deps.storage,
+        config.recipient,
+        amount,
+        cw20_amount,
+        "release_if_condition_met",
+    )
 }
 
-#[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
-    match msg {
-        QueryMsg::Arbiter {} => to_binary(&query_arbiter(deps)?),
+// runs the smart query of the condition and compares the raw response with the expected one
+fn is_condition_met(deps: Deps, condition: &ReleaseCondition) -> StdResult<bool> {
+    let request: QueryRequest<Empty> = WasmQuery::Smart {
+        contract_addr: condition.contract.to_string(),
+        msg: condition.query.clone(),
+    }
+    .into();
+    match deps.querier.raw_query(&to_vec(&request)?) {
+        SystemResult::Err(err) => Err(StdError::generic_err(format!(
+            "Querier system error: {}",
+            err
+        ))),
+        SystemResult::Ok(ContractResult::Err(err)) => Err(StdError::generic_err(format!(
+            "Querier contract error: {}",
+            err
+        ))),
+        SystemResult::Ok(ContractResult::Ok(response)) => Ok(response == condition.expected),
     }
 }
 
-fn query_arbiter(deps: Deps) -> StdResult<ArbiterResponse> {
+fn execute_approve_milestone(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    index: u32,
+) -> Result<Response, ContractError> {
     let config = CONFIG.load(deps.storage)?;
-    let addr = config.arbiter;
-    Ok(ArbiterResponse { arbiter: addr })
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
-    use cosmwasm_std::{coins, CosmosMsg, Timestamp};
-    use cw_utils::Expiration;
+    let weight = arbiter_weight(&config, &info.sender)?;
+    assert_not_disputed(deps.storage)?;
 
-    fn init_msg_expire_by_height(expiration: Option<Expiration>) -> InstantiateMsg {
-        InstantiateMsg {
-            arbiter: String::from("verifies"),
-            recipient: String::from("benefits"),
-            expiration,
+    // throws error if the contract is expired
+    if let Some(expiration) = config.expiration {
+        if expiration.is_expired(&env.block) {
+            return Err(ContractError::Expired { expiration });
         }
     }
 
-    #[test]
-    fn proper_initialization() {
-        let mut deps = mock_dependencies();
+    let mut milestones = MILESTONES
+        .may_load(deps.storage)?
+        .ok_or(ContractError::NoMilestones {})?;
+    let milestone = milestones
+        .get_mut(index as usize)
+        .ok_or(ContractError::MilestoneNotFound { index })?;
+    if milestone.released_at.is_some() {
+        return Err(ContractError::MilestoneReleased { index });
+    }
+    if is_expired(milestone.deadline, &env) {
+        return Err(ContractError::MilestoneExpired { index });
+    }
 
-        let msg = init_msg_expire_by_height(Some(Expiration::AtHeight(1000)));
-        let mut env = mock_env();
-        env.block.height = 876;
-        env.block.time = Timestamp::from_seconds(0);
-        let info = mock_info("creator", &coins(1000, "earth"));
+    let release = Release::Milestone {
+        recipient: config.recipient.clone(),
+        index,
+    };
+    if !vote(deps.storage, &config, &info.sender, weight, release)? {
+        return Ok(pending_response(&info.sender, "approve_milestone")
+            .add_attribute("index", index.to_string()));
+    }
 
-        let res = instantiate(deps.as_mut(), env, info, msg).unwrap();
-        assert_eq!(0, res.messages.len());
+    let held = native_balance(deps.as_ref(), &env)?;
+    sub_coins(&mut held.clone(), &milestone.amount)?;
+    reduce_deposits(deps.storage, &held, &milestone.amount)?;
+    milestone.released_at = Some(env.block.height);
+    let amount = milestone.amount.clone();
+    MILESTONES.save(deps.storage, &milestones)?;
 
-        // it worked, let's query the state
-        let state = CONFIG.load(&deps.storage).unwrap();
-        assert_eq!(
-            state,
-            Config {
-                arbiter: Addr::unchecked("verifies"),
-                recipient: Addr::unchecked("benefits"),
-                source: Addr::unchecked("creator"),
-                expiration: Some(Expiration::AtHeight(1000))
-            }
-        );
-    }
+    Ok(release_tokens(
+        deps.storage,
+        config.recipient,
+        amount,
+        vec![],
+        "approve_milestone",
+    )?
+    .add_attribute("index", index.to_string()))
+}
 
-    #[test]
-    fn cannot_initialize_expired() {
-        let mut deps = mock_dependencies();
+fn arbiter_weight(config: &Config, sender: &Addr) -> Result<u64, ContractError> {
+    config
+        .arbiters
+        .iter()
+        .find(|arbiter| &arbiter.addr == sender)
+        .map(|arbiter| arbiter.weight)
+        .ok_or(ContractError::Unauthorized {})
+}
 
-        let msg = init_msg_expire_by_height(Some(Expiration::AtHeight(1000)));
-        let mut env = mock_env();
-        env.block.height = 1001;
-        env.block.time = Timestamp::from_seconds(0);
-        let info = mock_info("creator", &coins(1000, "earth"));
+// records the arbiter's approval of the release, returns true once the threshold is reached
+fn vote(
+    storage: &mut dyn Storage,
+    config: &Config,
+    sender: &Addr,
+    weight: u64,
+    release: Release,
+) -> Result<bool, ContractError> {
+    let key = to_vec(&release)?;
+    let mut pending = PENDING_RELEASES
+        .may_load(storage, &key)?
+        .unwrap_or(PendingRelease {
+            release,
+            signers: vec![],
+            weight: 0,
+        });
+    if pending.signers.contains(sender) {
+        return Err(ContractError::AlreadyVoted {});
+    }
+    pending.signers.push(sender.clone());
+    pending.weight += weight;
 
-        let res = instantiate(deps.as_mut(), env, info, msg);
-        match res.unwrap_err() {
-            ContractError::Expired { .. } => {}
-            e => panic!("unexpected error: {:?}", e),
-        }
+    if pending.weight >= config.threshold {
+        PENDING_RELEASES.remove(storage, &key);
+        Ok(true)
+    } else {
+        PENDING_RELEASES.save(storage, &key, &pending)?;
+        Ok(false)
     }
+}
 
-    #[test]
-    fn init_and_query() {
-        let mut deps = mock_dependencies();
+fn pending_response(sender: &Addr, action: &str) -> Response {
+    Response::new()
+        .add_attribute("action", action)
+        .add_attribute("status", "pending")
+        .add_attribute("signer", sender)
+}
 
-        let arbiter = Addr::unchecked("arbiters");
-        let recipient = Addr::unchecked("receives");
-        let creator = Addr::unchecked("creates");
-        let msg = InstantiateMsg {
-            arbiter: arbiter.clone().into(),
-            recipient: recipient.into(),
-            expiration: None,
-        };
-        let mut env = mock_env();
-        env.block.height = 876;
-        env.block.time = Timestamp::from_seconds(0);
-        let info = mock_info(creator.as_str(), &[]);
-        let res = instantiate(deps.as_mut(), env, info, msg).unwrap();
-        assert_eq!(0, res.messages.len());
+fn assert_not_disputed(storage: &dyn Storage) -> Result<(), ContractError> {
+    if DISPUTE.may_load(storage)?.is_some() {
+        return Err(ContractError::Disputed {});
+    }
+    Ok(())
+}
 
-        // now let's query
-        let query_response = query_arbiter(deps.as_ref()).unwrap();
-        assert_eq!(query_response.arbiter, arbiter);
+fn execute_dispute(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    reason: String,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if info.sender != config.recipient && info.sender != config.source {
+        return Err(ContractError::Unauthorized {});
+    }
+    assert_not_disputed(deps.storage)?;
+    // a dispute would let the source block the claim and hand the swap to the arbiter
+    if HASHLOCK.may_load(deps.storage)?.is_some() {
+        return Err(ContractError::HashlockDefined {});
     }
 
-    #[test]
-    fn execute_approve() {
-        let mut deps = mock_dependencies();
+    // an expired escrow already belongs to the source
+    if let Some(expiration) = config.expiration {
+        if expiration.is_expired(&env.block) {
+            return Err(ContractError::Expired { expiration });
+        }
+    }
 
-        // initialize the store
-        let init_amount = coins(1000, "earth");
-        let msg = init_msg_expire_by_height(Some(Expiration::AtHeight(1000)));
-        let mut env = mock_env();
-        env.block.height = 876;
-        env.block.time = Timestamp::from_seconds(0);
-        let info = mock_info("creator", &init_amount);
-        let contract_addr = env.clone().contract.address;
-        let init_res = instantiate(deps.as_mut(), env, info, msg).unwrap();
-        assert_eq!(0, init_res.messages.len());
+    let dispute = Dispute {
+        raised_by: info.sender,
+        reason,
+        raised_at: env.block.height,
+    };
+    DISPUTE.save(deps.storage, &dispute)?;
 
-        // balance changed in init
-        deps.querier.update_balance(&contract_addr, init_amount);
+    Ok(Response::new()
+        .add_attribute("action", "dispute")
+        .add_attribute("raised_by", dispute.raised_by)
+        .add_attribute("reason", dispute.reason))
+}
 
-        // beneficiary cannot release it
-        let msg = ExecuteMsg::Approve { quantity: None };
-        let mut env = mock_env();
-        env.block.height = 900;
+fn execute_resolve(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    recipient_share: Decimal,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let weight = arbiter_weight(&config, &info.sender)?;
+    if DISPUTE.may_load(deps.storage)?.is_none() {
+        return Err(ContractError::NotDisputed {});
+    }
+    if recipient_share > Decimal::one() {
+        return Err(ContractError::InvalidShare {
+            share: recipient_share,
+        });
+    }
+
+    let release = Release::Resolve {
+        recipient: config.recipient.clone(),
+        recipient_share,
+    };
+    if !vote(deps.storage, &config, &info.sender, weight, release)? {
+        return Ok(pending_response(&info.sender, "resolve"));
+    }
+
+    let balance = native_balance(deps.as_ref(), &env)?;
+    let cw20_balance = cw20_balances(deps.as_ref())?;
+    let (recipient_amount, source_amount) = split_coins(&balance, recipient_share);
+    let (recipient_cw20, source_cw20) = split_cw20(&cw20_balance, recipient_share);
+    reduce_deposits(deps.storage, &balance, &recipient_amount)?;
+    release_cw20(deps.branch(), &cw20_balance)?;
+    DISPUTE.remove(deps.storage);
+
+    // only the share of the recipient pays the fee, like refunds the source's share is exempt
+    Ok(release_tokens(
+        deps.storage,
+        config.recipient,
+        recipient_amount,
+        recipient_cw20,
+        "resolve",
+    )?
+    .add_messages(refund_msgs(
+        deps.storage,
+        &config.source,
+        source_amount,
+        source_cw20,
+    )?)
+    .add_attribute("recipient_share", recipient_share.to_string()))
+}
+
+// vested tokens the recipient has not claimed yet
+fn claimable(vesting: &Vesting, now: Timestamp) -> Result<Vec<Coin>, ContractError> {
+    let mut claimable = vesting.vested(now);
+    sub_coins(&mut claimable, &vesting.claimed)?;
+    Ok(claimable)
+}
+
+fn execute_claim(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    preimage: Option<String>,
+) -> Result<Response, ContractError> {
+    match (HASHLOCK.may_load(deps.storage)?, preimage) {
+        (Some(hashlock), Some(preimage)) => claim_hashlocked(deps, env, hashlock, preimage),
+        (Some(_), None) => Err(ContractError::InvalidPreimage {}),
+        (None, Some(_)) => Err(ContractError::NoHashlock {}),
+        (None, None) => claim_vested(deps, env, info),
+    }
+}
+
+fn claim_hashlocked(
+    mut deps: DepsMut,
+    env: Env,
+    mut hashlock: Hashlock,
+    preimage: String,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    assert_not_disputed(deps.storage)?;
+
+    // throws error if the contract is expired
+    if let Some(expiration) = config.expiration {
+        if expiration.is_expired(&env.block) {
+            return Err(ContractError::Expired { expiration });
+        }
+    }
+    if hashlock.preimage.is_some() {
+        return Err(ContractError::NothingToClaim {});
+    }
+
+    let secret = hex::decode(&preimage).map_err(|_| ContractError::InvalidPreimage {})?;
+    if hex::encode(Sha256::digest(&secret)) != hashlock.hash {
+        return Err(ContractError::InvalidPreimage {});
+    }
+    hashlock.preimage = Some(hex::encode(secret));
+    HASHLOCK.save(deps.storage, &hashlock)?;
+
+    let amount = native_balance(deps.as_ref(), &env)?;
+    let cw20_amount = cw20_balances(deps.as_ref())?;
+    reduce_deposits(deps.storage, &amount, &amount)?;
+    release_cw20(deps.branch(), &cw20_amount)?;
+    Ok(
+        release_tokens(deps.storage, config.recipient, amount, cw20_amount, "claim")?
+            .add_attribute("preimage", preimage),
+    )
+}
+
+fn claim_vested(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if info.sender != config.recipient {
+        return Err(ContractError::Unauthorized {});
+    }
+    assert_not_disputed(deps.storage)?;
+
+    let mut vesting = VESTING
+        .may_load(deps.storage)?
+        .ok_or(ContractError::NoVesting {})?;
+    let amount = claimable(&vesting, env.block.time)?;
+    if amount.is_empty() {
+        return Err(ContractError::NothingToClaim {});
+    }
+
+    let held = native_balance(deps.as_ref(), &env)?;
+    sub_coins(&mut held.clone(), &amount)?;
+    reduce_deposits(deps.storage, &held, &amount)?;
+    add_coins(&mut vesting.claimed, &amount);
+    VESTING.save(deps.storage, &vesting)?;
+
+    release_tokens(deps.storage, config.recipient, amount, vec![], "claim")
+}
+
+fn execute_cancel_vesting(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let weight = arbiter_weight(&config, &info.sender)?;
+    assert_not_disputed(deps.storage)?;
+
+    let mut vesting = VESTING
+        .may_load(deps.storage)?
+        .ok_or(ContractError::NoVesting {})?;
+    if vesting.cancelled_at.is_some() {
+        return Err(ContractError::VestingCancelled {});
+    }
+
+    if !vote(
+        deps.storage,
+        &config,
+        &info.sender,
+        weight,
+        Release::CancelVesting {},
+    )? {
+        return Ok(pending_response(&info.sender, "cancel_vesting"));
+    }
+
+    vesting.cancelled_at = Some(env.block.time);
+    VESTING.save(deps.storage, &vesting)?;
+
+    // the recipient keeps what has vested so far, everything else goes back
+    let held = native_balance(deps.as_ref(), &env)?;
+    let vested = claimable(&vesting, env.block.time)?;
+    let mut refund = held.clone();
+    sub_coins(&mut refund, &vested)?;
+    reduce_deposits(deps.storage, &held, &vested)?;
+    let cw20_refund = cw20_balances(deps.as_ref())?;
+    release_cw20(deps.branch(), &cw20_refund)?;
+
+    refund_tokens(
+        deps.storage,
+        config.source,
+        refund,
+        cw20_refund,
+        "cancel_vesting",
+    )
+}
+
+fn execute_update_arbiter(
+    deps: DepsMut,
+    info: MessageInfo,
+    arbiter: String,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if info.sender != config.arbiter && info.sender != config.source {
+        return Err(ContractError::Unauthorized {});
+    }
+    let arbiter = deps.api.addr_validate(&arbiter)?;
+    if config.arbiters.iter().any(|a| a.addr == arbiter) || arbiter == config.arbiter {
+        return Err(ContractError::InvalidArbiters {});
+    }
+
+    let proposal = ArbiterProposal {
+        arbiter,
+        proposed_by: info.sender,
+    };
+    ARBITER_PROPOSAL.save(deps.storage, &proposal)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "propose_arbiter")
+        .add_attribute("arbiter", proposal.arbiter)
+        .add_attribute("proposed_by", proposal.proposed_by))
+}
+
+fn execute_accept_arbiter(
+    deps: DepsMut,
+    info: MessageInfo,
+    arbiter: String,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    let proposal = ARBITER_PROPOSAL
+        .may_load(deps.storage)?
+        .ok_or(ContractError::NoProposal {})?;
+    // the handover needs both the arbiter and the source
+    let accepting = if proposal.proposed_by == config.arbiter {
+        &config.source
+    } else {
+        &config.arbiter
+    };
+    if &info.sender != accepting {
+        return Err(ContractError::Unauthorized {});
+    }
+    // the proposal was replaced since the sender looked at it
+    if proposal.arbiter != arbiter {
+        return Err(ContractError::StaleProposal {
+            proposed: proposal.arbiter.into(),
+        });
+    }
+
+    // the new arbiter takes over the weight and the pending approvals of the old one
+    let previous = config.arbiter;
+    for arbiter in config.arbiters.iter_mut() {
+        if arbiter.addr == previous {
+            arbiter.addr = proposal.arbiter.clone();
+        }
+    }
+    let pending: Vec<_> = PENDING_RELEASES
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<_>>()?;
+    for (key, mut release) in pending {
+        if let Some(signer) = release.signers.iter_mut().find(|s| **s == previous) {
+            *signer = proposal.arbiter.clone();
+            PENDING_RELEASES.save(deps.storage, &key, &release)?;
+        }
+    }
+    config.arbiter = proposal.arbiter;
+    CONFIG.save(deps.storage, &config)?;
+    ARBITER_PROPOSAL.remove(deps.storage);
+
+    Ok(Response::new()
+        .add_attribute("action", "update_arbiter")
+        .add_attribute("previous", previous)
+        .add_attribute("arbiter", config.arbiter)
+        .add_attribute("accepted_by", info.sender))
+}
+
+fn execute_update_recipient(
+    deps: DepsMut,
+    info: MessageInfo,
+    recipient: String,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    if info.sender != config.recipient {
+        return Err(ContractError::Unauthorized {});
+    }
+    let previous = config.recipient;
+    config.recipient = deps.api.addr_validate(&recipient)?;
+    CONFIG.save(deps.storage, &config)?;
+
+    // approvals collected for the previous recipient can no longer be sent
+    let pending: Vec<_> = PENDING_RELEASES
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<_>>()?;
+    for (key, pending) in pending {
+        let stale = match pending.release {
+            Release::Approve { recipient, .. }
+            | Release::Milestone { recipient, .. }
+            | Release::Resolve { recipient, .. } => recipient == previous,
+            Release::CancelVesting {} => false,
+        };
+        if stale {
+            PENDING_RELEASES.remove(deps.storage, &key);
+        }
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "update_recipient")
+        .add_attribute("previous", previous)
+        .add_attribute("recipient", config.recipient))
+}
+
+fn execute_approve_escrow(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: String,
+    quantity: Option<Vec<Coin>>,
+    cw20_quantity: Option<Vec<Cw20Coin>>,
+) -> Result<Response, ContractError> {
+    let mut escrow = escrows().load(deps.storage, &id)?;
+    if info.sender != escrow.arbiter {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    // throws error if the escrow is expired
+    if let Some(expiration) = escrow.expiration {
+        if expiration.is_expired(&env.block) {
+            return Err(ContractError::Expired { expiration });
+        }
+    }
+
+    let (amount, cw20_amount) = if quantity.is_none() && cw20_quantity.is_none() {
+        // release everything
+        (
+            std::mem::take(&mut escrow.native_balance),
+            std::mem::take(&mut escrow.cw20_balance),
+        )
+    } else {
+        let quantity = quantity.unwrap_or_default();
+        let cw20_quantity = validate_cw20(deps.as_ref(), cw20_quantity)?;
+        sub_coins(&mut escrow.native_balance, &quantity)?;
+        sub_cw20(&mut escrow.cw20_balance, &cw20_quantity)?;
+        (quantity, cw20_quantity)
+    };
+    escrows().save(deps.storage, &id, &escrow)?;
+    track_registry_release(deps.storage, &amount)?;
+
+    Ok(release_tokens(
+        deps.storage,
+        escrow.recipient,
+        amount,
+        cw20_amount,
+        "approve",
+    )?
+    .add_attribute("id", id))
+}
+
+fn execute_refund(
+    mut deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+) -> Result<Response, ContractError> {
+    assert_not_disputed(deps.storage)?;
+    let config = CONFIG.load(deps.storage)?;
+    // anyone can try to refund, as long as the contract is expired
+    if let Some(expiration) = config.expiration {
+        if !expiration.is_expired(&env.block) {
+            return Err(ContractError::NotExpired {});
+        }
+    } else {
+        return Err(ContractError::NotExpired {});
+    }
+
+    let balance = native_balance(deps.as_ref(), &env)?;
+    let cw20_balance = cw20_balances(deps.as_ref())?;
+    release_cw20(deps.branch(), &cw20_balance)?;
+    refund_tokens(deps.storage, config.source, balance, cw20_balance, "refund")
+}
+
+fn execute_refund_escrow(
+    deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+    id: String,
+) -> Result<Response, ContractError> {
+    let escrow = escrows().load(deps.storage, &id)?;
+    // anyone can try to refund, as long as the escrow is expired
+    if let Some(expiration) = escrow.expiration {
+        if !expiration.is_expired(&env.block) {
+            return Err(ContractError::NotExpired {});
+        }
+    } else {
+        return Err(ContractError::NotExpired {});
+    }
+
+    // the escrow is fully paid out, so it is removed from the registry
+    escrows().remove(deps.storage, &id)?;
+    track_registry_release(deps.storage, &escrow.native_balance)?;
+
+    Ok(send_tokens(
+        escrow.source,
+        escrow.native_balance,
+        escrow.cw20_balance,
+        "refund",
+    )?
+    .add_attribute("id", id))
+}
+
+// native tokens held by the contract-level escrow, i.e. everything not held by registry escrows
+fn native_balance(deps: Deps, env: &Env) -> StdResult<Vec<Coin>> {
+    // Querier guarantees to return up-to-date data, including funds sent in this handle message
+    // https://github.com/CosmWasm/wasmd/blob/master/x/wasm/internal/keeper/keeper.go#L185-L192
+    let mut balance = deps.querier.query_all_balances(&env.contract.address)?;
+    let registry = REGISTRY_NATIVE_BALANCE
+        .may_load(deps.storage)?
+        .unwrap_or_default();
+    sub_coins(&mut balance, &registry).map_err(|err| StdError::generic_err(err.to_string()))?;
+    Ok(balance)
+}
+
+fn track_registry_deposit(storage: &mut dyn Storage, amount: &[Coin]) -> StdResult<()> {
+    let mut registry = REGISTRY_NATIVE_BALANCE
+        .may_load(storage)?
+        .unwrap_or_default();
+    add_coins(&mut registry, amount);
+    REGISTRY_NATIVE_BALANCE.save(storage, &registry)
+}
+
+fn track_registry_release(storage: &mut dyn Storage, amount: &[Coin]) -> Result<(), ContractError> {
+    let mut registry = REGISTRY_NATIVE_BALANCE
+        .may_load(storage)?
+        .unwrap_or_default();
+    sub_coins(&mut registry, amount)?;
+    Ok(REGISTRY_NATIVE_BALANCE.save(storage, &registry)?)
+}
+
+fn validate_cw20(
+    deps: Deps,
+    cw20_quantity: Option<Vec<Cw20Coin>>,
+) -> StdResult<Vec<Cw20CoinVerified>> {
+    cw20_quantity
+        .unwrap_or_default()
+        .into_iter()
+        .map(|coin| {
+            Ok(Cw20CoinVerified {
+                address: deps.api.addr_validate(&coin.address)?,
+                amount: coin.amount,
+            })
+        })
+        .collect()
+}
+
+fn cw20_balances(deps: Deps) -> StdResult<Vec<Cw20CoinVerified>> {
+    CW20_BALANCES
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| {
+            let (address, amount) = item?;
+            Ok(Cw20CoinVerified { address, amount })
+        })
+        .collect()
+}
+
+// deducts the released cw20 tokens from the tracked balances
+fn release_cw20(deps: DepsMut, amount: &[Cw20CoinVerified]) -> Result<(), ContractError> {
+    for coin in amount {
+        let balance = CW20_BALANCES
+            .may_load(deps.storage, &coin.address)?
+            .unwrap_or_default();
+        let remaining =
+            balance
+                .checked_sub(coin.amount)
+                .map_err(|_| ContractError::InsufficientBalance {
+                    token: coin.address.to_string(),
+                })?;
+        if remaining == Uint128::zero() {
+            CW20_BALANCES.remove(deps.storage, &coin.address);
+        } else {
+            CW20_BALANCES.save(deps.storage, &coin.address, &remaining)?;
+        }
+    }
+    Ok(())
+}
+
+// pays out to the recipient, minus the fee if one is configured
+fn release_tokens(
+    storage: &mut dyn Storage,
+    recipient: Addr,
+    amount: Vec<Coin>,
+    cw20_amount: Vec<Cw20CoinVerified>,
+    action: &str,
+) -> Result<Response, ContractError> {
+    let fee = match FEE.may_load(storage)? {
+        Some(fee) => fee,
+        None => return send_tokens(recipient, amount, cw20_amount, action),
+    };
+    // the fee is rounded down, so the recipient receives any leftover dust
+    let (fee_amount, amount) = split_coins(&amount, fee.rate);
+    let (fee_cw20, cw20_amount) = split_cw20(&cw20_amount, fee.rate);
+    let mut collected = FEES_COLLECTED.may_load(storage)?.unwrap_or_default();
+    add_coins(&mut collected.native, &fee_amount);
+    add_cw20(&mut collected.cw20, &fee_cw20);
+    FEES_COLLECTED.save(storage, &collected)?;
+
+    Ok(send_tokens(recipient, amount, cw20_amount, action)?
+        .add_messages(transfer_msgs(&fee.collector, fee_amount, fee_cw20)?)
+        .add_attribute("fee_collector", fee.collector))
+}
+
+// pays a refund back to the depositors, see refund_msgs
+fn refund_tokens(
+    storage: &mut dyn Storage,
+    source: Addr,
+    amount: Vec<Coin>,
+    cw20_amount: Vec<Cw20CoinVerified>,
+    action: &str,
+) -> Result<Response, ContractError> {
+    Ok(Response::new()
+        .add_messages(refund_msgs(storage, &source, amount, cw20_amount)?)
+        .add_attribute("action", action)
+        .add_attribute("to", source))
+}
+
+// every release is paid out of the deposits in proportion, so that the recorded deposits
+// keep matching what each depositor still has in the escrow and a later refund does not
+// pay out what was already released
+fn reduce_deposits(storage: &mut dyn Storage, held: &[Coin], released: &[Coin]) -> StdResult<()> {
+    let amount_of = |coins: &[Coin], denom: &str| {
+        coins
+            .iter()
+            .find(|c| c.denom == denom)
+            .map(|c| c.amount)
+            .unwrap_or_default()
+    };
+    let deposits = DEPOSITS
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for (depositor, mut deposit) in deposits {
+        for coin in deposit.iter_mut() {
+            let held = amount_of(held, &coin.denom);
+            let released = amount_of(released, &coin.denom);
+            coin.amount = if held.is_zero() {
+                Uint128::zero()
+            } else {
+                coin.amount
+                    .multiply_ratio(held.saturating_sub(released), held)
+            };
+        }
+        deposit.retain(|c| !c.amount.is_zero());
+        if deposit.is_empty() {
+            DEPOSITS.remove(storage, &depositor);
+        } else {
+            DEPOSITS.save(storage, &depositor, &deposit)?;
+        }
+    }
+    Ok(())
+}
+
+// splits a refund of native tokens between the recorded depositors in proportion to their
+// deposits, never paying anyone more than they deposited, and clears the deposits. The
+// source receives the rest, including untracked transfers and all cw20 tokens.
+fn refund_msgs(
+    storage: &mut dyn Storage,
+    source: &Addr,
+    mut amount: Vec<Coin>,
+    cw20_amount: Vec<Cw20CoinVerified>,
+) -> Result<Vec<CosmosMsg>, ContractError> {
+    let deposits = DEPOSITS
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    let held = amount.clone();
+    let mut msgs = vec![];
+    for (depositor, deposit) in &deposits {
+        DEPOSITS.remove(storage, depositor);
+        // the share of the source stays in the rest
+        if depositor == source {
+            continue;
+        }
+        let share: Vec<Coin> = deposit
+            .iter()
+            .filter_map(|coin| {
+                let held = held.iter().find(|c| c.denom == coin.denom)?.amount;
+                let total: Uint128 = deposits
+                    .iter()
+                    .flat_map(|(_, deposit)| deposit)
+                    .filter(|c| c.denom == coin.denom)
+                    .map(|c| c.amount)
+                    .sum();
+                let amount = held.multiply_ratio(coin.amount, total).min(coin.amount);
+                (!amount.is_zero()).then(|| Coin {
+                    denom: coin.denom.clone(),
+                    amount,
+                })
+            })
+            .collect();
+        sub_coins(&mut amount, &share)?;
+        msgs.extend(transfer_msgs(depositor, share, vec![])?);
+    }
+    msgs.extend(transfer_msgs(source, amount, cw20_amount)?);
+    Ok(msgs)
+}
+
+// this is a helper to move the tokens, so the business logic is easy to read
+fn send_tokens(
+    to_address: Addr,
+    amount: Vec<Coin>,
+    cw20_amount: Vec<Cw20CoinVerified>,
+    action: &str,
+) -> Result<Response, ContractError> {
+    Ok(Response::new()
+        .add_messages(transfer_msgs(&to_address, amount, cw20_amount)?)
+        .add_attribute("action", action)
+        .add_attribute("to", to_address))
+}
+
+fn transfer_msgs(
+    to_address: &Addr,
+    amount: Vec<Coin>,
+    cw20_amount: Vec<Cw20CoinVerified>,
+) -> StdResult<Vec<CosmosMsg>> {
+    let mut msgs = vec![];
+    if !amount.is_empty() {
+        msgs.push(
+            BankMsg::Send {
+                to_address: to_address.into(),
+                amount,
+            }
+            .into(),
+        );
+    }
+    for coin in cw20_amount {
+        msgs.push(Cw20Contract(coin.address).call(Cw20ExecuteMsg::Transfer {
+            recipient: to_address.into(),
+            amount: coin.amount,
+        })?);
+    }
+    Ok(msgs)
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::Arbiter {} => to_binary(&query_arbiter(deps)?),
+        QueryMsg::Details { id: None } => to_binary(&query_config_details(deps, env)?),
+        QueryMsg::Details { id: Some(id) } => to_binary(&query_details(deps, env, id)?),
+        QueryMsg::Vesting {} => to_binary(&query_vesting(deps, env)?),
+        QueryMsg::ArbiterProposal {} => to_binary(&ArbiterProposalResponse {
+            proposal: ARBITER_PROPOSAL.may_load(deps.storage)?,
+        }),
+        QueryMsg::ReleaseCondition {} => to_binary(&query_release_condition(deps)?),
+        QueryMsg::Fee {} => to_binary(&FeeResponse {
+            fee: FEE.may_load(deps.storage)?,
+            collected: FEES_COLLECTED.may_load(deps.storage)?.unwrap_or_default(),
+        }),
+        QueryMsg::Hashlock {} => to_binary(&HashlockResponse {
+            hashlock: HASHLOCK.may_load(deps.storage)?,
+        }),
+        QueryMsg::Dispute {} => to_binary(&DisputeResponse {
+            dispute: DISPUTE.may_load(deps.storage)?,
+        }),
+        QueryMsg::PendingReleases {} => to_binary(&query_pending_releases(deps)?),
+        QueryMsg::Milestones {} => to_binary(&query_milestones(deps, env)?),
+        QueryMsg::Deposits { start_after, limit } => {
+            to_binary(&query_deposits(deps, start_after, limit)?)
+        }
+        QueryMsg::List { start_after, limit } => to_binary(&query_list(deps, start_after, limit)?),
+        QueryMsg::ListByArbiter {
+            arbiter,
+            start_after,
+            limit,
+        } => to_binary(&query_list_by_arbiter(deps, arbiter, start_after, limit)?),
+        QueryMsg::ListByRecipient {
+            recipient,
+            start_after,
+            limit,
+        } => to_binary(&query_list_by_recipient(
+            deps,
+            recipient,
+            start_after,
+            limit,
+        )?),
+    }
+}
+
+fn query_arbiter(deps: Deps) -> StdResult<ArbiterResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    let addr = config.arbiter;
+    Ok(ArbiterResponse { arbiter: addr })
+}
+
+fn is_expired(expiration: Option<Expiration>, env: &Env) -> bool {
+    matches!(expiration, Some(expiration) if expiration.is_expired(&env.block))
+}
+
+fn query_config_details(deps: Deps, env: Env) -> StdResult<DetailsResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    Ok(DetailsResponse {
+        arbiter: config.arbiter,
+        arbiters: config.arbiters,
+        threshold: config.threshold,
+        recipient: config.recipient,
+        source: config.source,
+        expiration: config.expiration,
+        is_expired: is_expired(config.expiration, &env),
+        cw20_whitelist: config.cw20_whitelist,
+        native_balance: native_balance(deps, &env)?,
+        cw20_balance: cw20_balances(deps)?,
+    })
+}
+
+fn query_vesting(deps: Deps, env: Env) -> StdResult<VestingResponse> {
+    let vesting = VESTING.load(deps.storage)?;
+    let claimable = claimable(&vesting, env.block.time)
+        .map_err(|err| StdError::generic_err(err.to_string()))?;
+    Ok(VestingResponse {
+        vested: vesting.vested(env.block.time),
+        amount: vesting.amount,
+        start: vesting.start,
+        cliff: vesting.cliff,
+        end: vesting.end,
+        cancelled_at: vesting.cancelled_at,
+        claimed: vesting.claimed,
+        claimable,
+    })
+}
+
+fn query_release_condition(deps: Deps) -> StdResult<ReleaseConditionResponse> {
+    let condition = RELEASE_CONDITION.may_load(deps.storage)?;
+    let is_met = match &condition {
+        Some(condition) => is_condition_met(deps, condition)?,
+        None => false,
+    };
+    Ok(ReleaseConditionResponse { condition, is_met })
+}
+
+fn query_pending_releases(deps: Deps) -> StdResult<PendingReleasesResponse> {
+    let releases: StdResult<Vec<_>> = PENDING_RELEASES
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, release)| release))
+        .collect();
+    Ok(PendingReleasesResponse {
+        releases: releases?,
+    })
+}
+
+fn query_milestones(deps: Deps, env: Env) -> StdResult<MilestonesResponse> {
+    let milestones = MILESTONES
+        .may_load(deps.storage)?
+        .unwrap_or_default()
+        .into_iter()
+        .map(|milestone| {
+            let status = if milestone.released_at.is_some() {
+                MilestoneStatus::Released
+            } else if is_expired(milestone.deadline, &env) {
+                MilestoneStatus::Expired
+            } else {
+                MilestoneStatus::Pending
+            };
+            MilestoneResponse {
+                title: milestone.title,
+                amount: milestone.amount,
+                deadline: milestone.deadline,
+                status,
+                released_at: milestone.released_at,
+            }
+        })
+        .collect();
+    Ok(MilestonesResponse { milestones })
+}
+
+fn query_details(deps: Deps, env: Env, id: String) -> StdResult<DetailsResponse> {
+    let escrow = escrows().load(deps.storage, &id)?;
+    Ok(DetailsResponse {
+        arbiter: escrow.arbiter.clone(),
+        arbiters: vec![Arbiter {
+            addr: escrow.arbiter,
+            weight: 1,
+        }],
+        threshold: 1,
+        recipient: escrow.recipient,
+        source: escrow.source,
+        expiration: escrow.expiration,
+        is_expired: is_expired(escrow.expiration, &env),
+        cw20_whitelist: escrow.cw20_whitelist,
+        native_balance: escrow.native_balance,
+        cw20_balance: escrow.cw20_balance,
+    })
+}
+
+// Limits for pagination
+const MAX_LIMIT: u32 = 30;
+const DEFAULT_LIMIT: u32 = 10;
+
+fn query_list(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<ListResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.as_deref().map(Bound::exclusive);
+    let escrows: StdResult<Vec<_>> = escrows()
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .collect();
+    Ok(ListResponse { escrows: escrows? })
+}
+
+fn query_deposits(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<DepositsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start_after = start_after
+        .map(|addr| deps.api.addr_validate(&addr))
+        .transpose()?;
+    let start = start_after.as_ref().map(Bound::exclusive);
+    let deposits: StdResult<Vec<_>> = DEPOSITS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (depositor, amount) = item?;
+            Ok(DepositInfo { depositor, amount })
+        })
+        .collect();
+    Ok(DepositsResponse {
+        deposits: deposits?,
+    })
+}
+
+fn query_list_by_arbiter(
+    deps: Deps,
+    arbiter: String,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<ListResponse> {
+    let arbiter = deps.api.addr_validate(&arbiter)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+    let escrows: StdResult<Vec<_>> = escrows()
+        .idx
+        .arbiter
+        .prefix(arbiter)
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .collect();
+    Ok(ListResponse { escrows: escrows? })
+}
+
+fn query_list_by_recipient(
+    deps: Deps,
+    recipient: String,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<ListResponse> {
+    let recipient = deps.api.addr_validate(&recipient)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+    let escrows: StdResult<Vec<_>> = escrows()
+        .idx
+        .recipient
+        .prefix(recipient)
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .collect();
+    Ok(ListResponse { escrows: escrows? })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::msg::{FeeMsg, ReleaseConditionMsg};
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cosmwasm_std::{coin, coins, CosmosMsg, Timestamp, WasmMsg};
+    use cw_utils::Expiration;
+
+    fn init_msg_expire_by_height(expiration: Option<Expiration>) -> InstantiateMsg {
+        InstantiateMsg {
+            arbiter: String::from("verifies"),
+            recipient: String::from("benefits"),
+            expiration,
+            cw20_whitelist: None,
+            milestones: None,
+            arbiters: None,
+            threshold: None,
+            vesting: None,
+            release_condition: None,
+            hashlock: None,
+            fee: None,
+        }
+    }
+
+    #[test]
+    fn proper_initialization() {
+        let mut deps = mock_dependencies();
+
+        let msg = init_msg_expire_by_height(Some(Expiration::AtHeight(1000)));
+        let mut env = mock_env();
+        env.block.height = 876;
+        env.block.time = Timestamp::from_seconds(0);
+        let info = mock_info("creator", &coins(1000, "earth"));
+
+        let res = instantiate(deps.as_mut(), env, info, msg).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        // it worked, let's query the state
+        let state = CONFIG.load(&deps.storage).unwrap();
+        assert_eq!(
+            state,
+            Config {
+                arbiter: Addr::unchecked("verifies"),
+                recipient: Addr::unchecked("benefits"),
+                source: Addr::unchecked("creator"),
+                expiration: Some(Expiration::AtHeight(1000)),
+                cw20_whitelist: vec![],
+                arbiters: vec![Arbiter {
+                    addr: Addr::unchecked("verifies"),
+                    weight: 1,
+                }],
+                threshold: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn cannot_initialize_expired() {
+        let mut deps = mock_dependencies();
+
+        let msg = init_msg_expire_by_height(Some(Expiration::AtHeight(1000)));
+        let mut env = mock_env();
+        env.block.height = 1001;
+        env.block.time = Timestamp::from_seconds(0);
+        let info = mock_info("creator", &coins(1000, "earth"));
+
+        let res = instantiate(deps.as_mut(), env, info, msg);
+        match res.unwrap_err() {
+            ContractError::Expired { .. } => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn init_and_query() {
+        let mut deps = mock_dependencies();
+
+        let arbiter = Addr::unchecked("arbiters");
+        let recipient = Addr::unchecked("receives");
+        let creator = Addr::unchecked("creates");
+        let msg = InstantiateMsg {
+            arbiter: arbiter.clone().into(),
+            recipient: recipient.into(),
+            expiration: None,
+            cw20_whitelist: None,
+            milestones: None,
+            arbiters: None,
+            threshold: None,
+            vesting: None,
+            release_condition: None,
+            hashlock: None,
+            fee: None,
+        };
+        let mut env = mock_env();
+        env.block.height = 876;
+        env.block.time = Timestamp::from_seconds(0);
+        let info = mock_info(creator.as_str(), &[]);
+        let res = instantiate(deps.as_mut(), env, info, msg).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        // now let's query
+        let query_response = query_arbiter(deps.as_ref()).unwrap();
+        assert_eq!(query_response.arbiter, arbiter);
+    }
+
+    #[test]
+    fn execute_approve() {
+        let mut deps = mock_dependencies();
+
+        // initialize the store
+        let init_amount = coins(1000, "earth");
+        let msg = init_msg_expire_by_height(Some(Expiration::AtHeight(1000)));
+        let mut env = mock_env();
+        env.block.height = 876;
+        env.block.time = Timestamp::from_seconds(0);
+        let info = mock_info("creator", &init_amount);
+        let contract_addr = env.clone().contract.address;
+        let init_res = instantiate(deps.as_mut(), env, info, msg).unwrap();
+        assert_eq!(0, init_res.messages.len());
+
+        // balance changed in init
+        deps.querier.update_balance(&contract_addr, init_amount);
+
+        // beneficiary cannot release it
+        let msg = ExecuteMsg::Approve {
+            id: None,
+            quantity: None,
+            cw20_quantity: None,
+        };
+        let mut env = mock_env();
+        env.block.height = 900;
         env.block.time = Timestamp::from_seconds(0);
         let info = mock_info("beneficiary", &[]);
         let execute_res = execute(deps.as_mut(), env, info, msg.clone());
@@ -232,146 +1660,2008 @@ mod tests {
             e => panic!("unexpected error: {:?}", e),
         }
 
-        // verifier cannot release it when expired
+        // verifier cannot release it when expired
+        let mut env = mock_env();
+        env.block.height = 1100;
+        env.block.time = Timestamp::from_seconds(0);
+        let info = mock_info("verifies", &[]);
+        let execute_res = execute(deps.as_mut(), env, info, msg.clone());
+        match execute_res.unwrap_err() {
+            ContractError::Expired { .. } => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        // complete release by verifier, before expiration
+        let mut env = mock_env();
+        env.block.height = 999;
+        env.block.time = Timestamp::from_seconds(0);
+        let info = mock_info("verifies", &[]);
+        let execute_res = execute(deps.as_mut(), env, info, msg).unwrap();
+        assert_eq!(1, execute_res.messages.len());
+        let msg = execute_res.messages.first().expect("no message");
+        assert_eq!(
+            msg.msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "benefits".into(),
+                amount: coins(1000, "earth"),
+            })
+        );
+
+        // partial release by verifier, before expiration
+        let partial_msg = ExecuteMsg::Approve {
+            id: None,
+            quantity: Some(coins(500, "earth")),
+            cw20_quantity: None,
+        };
+        let mut env = mock_env();
+        env.block.height = 999;
+        env.block.time = Timestamp::from_seconds(0);
+        let info = mock_info("verifies", &[]);
+        let execute_res = execute(deps.as_mut(), env, info, partial_msg).unwrap();
+        assert_eq!(1, execute_res.messages.len());
+        let msg = execute_res.messages.first().expect("no message");
+        assert_eq!(
+            msg.msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "benefits".into(),
+                amount: coins(500, "earth"),
+            })
+        );
+    }
+
+    #[test]
+    fn handle_refund() {
+        let mut deps = mock_dependencies();
+
+        // initialize the store
+        let init_amount = coins(1000, "earth");
+        let msg = init_msg_expire_by_height(Some(Expiration::AtHeight(1000)));
+        let mut env = mock_env();
+        env.block.height = 876;
+        env.block.time = Timestamp::from_seconds(0);
+        let info = mock_info("creator", &init_amount);
+        let contract_addr = env.clone().contract.address;
+        let init_res = instantiate(deps.as_mut(), env, info, msg).unwrap();
+        assert_eq!(0, init_res.messages.len());
+
+        // balance changed in init
+        deps.querier.update_balance(&contract_addr, init_amount);
+
+        // cannot release when unexpired (height < Expiration::AtHeight(1000))
+        let msg = ExecuteMsg::Refund { id: None };
+        let mut env = mock_env();
+        env.block.height = 800;
+        env.block.time = Timestamp::from_seconds(0);
+        let info = mock_info("anybody", &[]);
+        let execute_res = execute(deps.as_mut(), env, info, msg);
+        match execute_res.unwrap_err() {
+            ContractError::NotExpired { .. } => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        // Contract expires when height == Expiration::AtHeight(1000)
+        let msg = ExecuteMsg::Refund { id: None };
+        let mut env = mock_env();
+        env.block.height = 1000;
+        env.block.time = Timestamp::from_seconds(0);
+        let info = mock_info("anybody", &[]);
+        let execute_res = execute(deps.as_mut(), env, info, msg).unwrap();
+        assert_eq!(1, execute_res.messages.len());
+        let msg = execute_res.messages.first().expect("no message");
+        assert_eq!(
+            msg.msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(1000, "earth"),
+            })
+        );
+
+        // anyone can release after expiration
+        let msg = ExecuteMsg::Refund { id: None };
+        let mut env = mock_env();
+        env.block.height = 1001;
+        env.block.time = Timestamp::from_seconds(0);
+        let info = mock_info("anybody", &[]);
+        let execute_res = execute(deps.as_mut(), env, info, msg).unwrap();
+        assert_eq!(1, execute_res.messages.len());
+        let msg = execute_res.messages.first().expect("no message");
+        assert_eq!(
+            msg.msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(1000, "earth"),
+            })
+        );
+    }
+
+    #[test]
+    fn handle_refund_no_expiration() {
+        let mut deps = mock_dependencies();
+
+        // initialize the store
+        let init_amount = coins(1000, "earth");
+        let msg = init_msg_expire_by_height(None);
+        let mut env = mock_env();
+        env.block.height = 876;
+        env.block.time = Timestamp::from_seconds(0);
+        let info = mock_info("creator", &init_amount);
+        let contract_addr = env.clone().contract.address;
+        let init_res = instantiate(deps.as_mut(), env, info, msg).unwrap();
+        assert_eq!(0, init_res.messages.len());
+
+        // balance changed in init
+        deps.querier.update_balance(&contract_addr, init_amount);
+
+        // cannot release when unexpired (no expiration)
+        let msg = ExecuteMsg::Refund { id: None };
+        let mut env = mock_env();
+        env.block.height = 800;
+        env.block.time = Timestamp::from_seconds(0);
+        let info = mock_info("anybody", &[]);
+        let execute_res = execute(deps.as_mut(), env, info, msg);
+        match execute_res.unwrap_err() {
+            ContractError::NotExpired { .. } => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    fn cw20_transfer(token: &str, recipient: &str, amount: u128) -> CosmosMsg {
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: token.into(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: recipient.into(),
+                amount: Uint128::new(amount),
+            })
+            .unwrap(),
+            funds: vec![],
+        })
+    }
+
+    fn cw20_deposit(amount: u128) -> ExecuteMsg {
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: String::from("depositor"),
+            amount: Uint128::new(amount),
+            msg: to_binary(&ReceiveMsg::Deposit { id: None }).unwrap(),
+        })
+    }
+
+    #[test]
+    fn cw20_deposit_and_approve() {
+        let mut deps = mock_dependencies();
+
+        // initialize the store
+        let init_amount = coins(1000, "earth");
+        let msg = InstantiateMsg {
+            cw20_whitelist: Some(vec![String::from("token")]),
+            ..init_msg_expire_by_height(Some(Expiration::AtHeight(1000)))
+        };
+        let mut env = mock_env();
+        env.block.height = 876;
+        let info = mock_info("creator", &init_amount);
+        let contract_addr = env.clone().contract.address;
+        instantiate(deps.as_mut(), env, info, msg).unwrap();
+        deps.querier.update_balance(&contract_addr, init_amount);
+
+        // only whitelisted tokens can be deposited
+        let info = mock_info("other_token", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, cw20_deposit(100)).unwrap_err();
+        match err {
+            ContractError::NotInWhitelist { token } => assert_eq!(token, "other_token"),
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        let info = mock_info("token", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), cw20_deposit(100)).unwrap();
+        assert_eq!(0, res.messages.len());
+        execute(deps.as_mut(), mock_env(), info, cw20_deposit(50)).unwrap();
+        let balance = CW20_BALANCES
+            .load(&deps.storage, &Addr::unchecked("token"))
+            .unwrap();
+        assert_eq!(balance, Uint128::new(150));
+
+        // cannot release more cw20 than deposited
+        let mut env = mock_env();
+        env.block.height = 900;
+        let info = mock_info("verifies", &[]);
+        let msg = ExecuteMsg::Approve {
+            id: None,
+            quantity: None,
+            cw20_quantity: Some(vec![Cw20Coin {
+                address: String::from("token"),
+                amount: Uint128::new(151),
+            }]),
+        };
+        let err = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+        match err {
+            ContractError::InsufficientBalance { token } => assert_eq!(token, "token"),
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        // partial cw20 release leaves native coins untouched
+        let msg = ExecuteMsg::Approve {
+            id: None,
+            quantity: None,
+            cw20_quantity: Some(vec![Cw20Coin {
+                address: String::from("token"),
+                amount: Uint128::new(30),
+            }]),
+        };
+        let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        assert_eq!(1, res.messages.len());
+        assert_eq!(res.messages[0].msg, cw20_transfer("token", "benefits", 30));
+
+        // complete release sends both native and cw20 tokens
+        let msg = ExecuteMsg::Approve {
+            id: None,
+            quantity: None,
+            cw20_quantity: None,
+        };
+        let res = execute(deps.as_mut(), env, info, msg).unwrap();
+        assert_eq!(2, res.messages.len());
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "benefits".into(),
+                amount: coins(1000, "earth"),
+            })
+        );
+        assert_eq!(res.messages[1].msg, cw20_transfer("token", "benefits", 120));
+        assert!(CW20_BALANCES
+            .may_load(&deps.storage, &Addr::unchecked("token"))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn cw20_refund() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            cw20_whitelist: Some(vec![String::from("token")]),
+            ..init_msg_expire_by_height(Some(Expiration::AtHeight(1000)))
+        };
+        let mut env = mock_env();
+        env.block.height = 876;
+        let info = mock_info("creator", &[]);
+        instantiate(deps.as_mut(), env, info, msg).unwrap();
+
+        let info = mock_info("token", &[]);
+        execute(deps.as_mut(), mock_env(), info, cw20_deposit(100)).unwrap();
+
+        // only cw20 tokens are held, so no bank message is sent
+        let mut env = mock_env();
+        env.block.height = 1000;
+        let info = mock_info("anybody", &[]);
+        let res = execute(deps.as_mut(), env, info, ExecuteMsg::Refund { id: None }).unwrap();
+        assert_eq!(1, res.messages.len());
+        assert_eq!(res.messages[0].msg, cw20_transfer("token", "creator", 100));
+    }
+
+    fn create_msg(id: &str, arbiter: &str, recipient: &str) -> ExecuteMsg {
+        ExecuteMsg::Create {
+            id: id.to_string(),
+            arbiter: arbiter.to_string(),
+            recipient: recipient.to_string(),
+            expiration: Some(Expiration::AtHeight(1000)),
+            cw20_whitelist: Some(vec![String::from("token")]),
+        }
+    }
+
+    #[test]
+    fn registry_create_and_list() {
+        let mut deps = mock_dependencies();
+
+        let msg = init_msg_expire_by_height(None);
+        let info = mock_info("creator", &[]);
+        instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let mut env = mock_env();
+        env.block.height = 876;
+        let info = mock_info("source", &coins(100, "earth"));
+        for (id, arbiter, recipient) in [
+            ("deal-1", "alice", "bob"),
+            ("deal-2", "alice", "carol"),
+            ("deal-3", "dave", "bob"),
+        ] {
+            let msg = create_msg(id, arbiter, recipient);
+            execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        }
+
+        // ids cannot be reused
+        let msg = create_msg("deal-1", "alice", "bob");
+        let err = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+        match err {
+            ContractError::AlreadyInUse { id } => assert_eq!(id, "deal-1"),
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        // cannot create an expired escrow
+        env.block.height = 1000;
+        let msg = create_msg("deal-4", "alice", "bob");
+        let err = execute(deps.as_mut(), env, info, msg).unwrap_err();
+        match err {
+            ContractError::Expired { .. } => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        let mut env = mock_env();
+        env.block.height = 876;
+        let details = query_details(deps.as_ref(), env, String::from("deal-2")).unwrap();
+        assert_eq!(
+            details,
+            DetailsResponse {
+                arbiter: Addr::unchecked("alice"),
+                arbiters: vec![Arbiter {
+                    addr: Addr::unchecked("alice"),
+                    weight: 1,
+                }],
+                threshold: 1,
+                recipient: Addr::unchecked("carol"),
+                source: Addr::unchecked("source"),
+                expiration: Some(Expiration::AtHeight(1000)),
+                is_expired: false,
+                cw20_whitelist: vec![Addr::unchecked("token")],
+                native_balance: coins(100, "earth"),
+                cw20_balance: vec![],
+            }
+        );
+
+        let list = query_list(deps.as_ref(), None, None).unwrap();
+        assert_eq!(list.escrows, vec!["deal-1", "deal-2", "deal-3"]);
+        let list = query_list(deps.as_ref(), Some(String::from("deal-1")), Some(1)).unwrap();
+        assert_eq!(list.escrows, vec!["deal-2"]);
+
+        let list = query_list_by_arbiter(deps.as_ref(), String::from("alice"), None, None).unwrap();
+        assert_eq!(list.escrows, vec!["deal-1", "deal-2"]);
+        let list = query_list_by_arbiter(
+            deps.as_ref(),
+            String::from("alice"),
+            Some(String::from("deal-1")),
+            None,
+        )
+        .unwrap();
+        assert_eq!(list.escrows, vec!["deal-2"]);
+
+        let list = query_list_by_recipient(deps.as_ref(), String::from("bob"), None, None).unwrap();
+        assert_eq!(list.escrows, vec!["deal-1", "deal-3"]);
+    }
+
+    #[test]
+    fn registry_deposit_approve_and_refund() {
+        let mut deps = mock_dependencies();
+
+        let msg = init_msg_expire_by_height(None);
+        let info = mock_info("creator", &coins(1000, "earth"));
+        let env = mock_env();
+        let contract_addr = env.contract.address.clone();
+        instantiate(deps.as_mut(), env, info, msg).unwrap();
+
+        let mut env = mock_env();
+        env.block.height = 876;
+        let info = mock_info("source", &coins(100, "earth"));
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            info,
+            create_msg("deal", "alice", "bob"),
+        )
+        .unwrap();
+        let info = mock_info("anyone", &coins(50, "earth"));
+        let msg = ExecuteMsg::Deposit {
+            id: String::from("deal"),
+        };
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: String::from("depositor"),
+            amount: Uint128::new(70),
+            msg: to_binary(&ReceiveMsg::Deposit {
+                id: Some(String::from("deal")),
+            })
+            .unwrap(),
+        });
+        execute(deps.as_mut(), env.clone(), mock_info("token", &[]), msg).unwrap();
+        deps.querier
+            .update_balance(&contract_addr, coins(1150, "earth"));
+
+        // the contract-level escrow cannot touch coins held for the registry
+        let info = mock_info("verifies", &[]);
+        let msg = ExecuteMsg::Approve {
+            id: None,
+            quantity: Some(coins(1001, "earth")),
+            cw20_quantity: None,
+        };
+        let err = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+        match err {
+            ContractError::InsufficientBalance { token } => assert_eq!(token, "earth"),
+            e => panic!("unexpected error: {:?}", e),
+        }
+        let msg = ExecuteMsg::Approve {
+            id: None,
+            quantity: None,
+            cw20_quantity: None,
+        };
+        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "benefits".into(),
+                amount: coins(1000, "earth"),
+            })
+        );
+        deps.querier
+            .update_balance(&contract_addr, coins(150, "earth"));
+
+        // only the escrow's arbiter can approve
+        let msg = ExecuteMsg::Approve {
+            id: Some(String::from("deal")),
+            quantity: Some(coins(40, "earth")),
+            cw20_quantity: None,
+        };
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("verifies", &[]),
+            msg.clone(),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        // partial release
+        let res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        assert_eq!(1, res.messages.len());
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "bob".into(),
+                amount: coins(40, "earth"),
+            })
+        );
+        let details = query_details(deps.as_ref(), mock_env(), String::from("deal")).unwrap();
+        assert_eq!(details.native_balance, coins(110, "earth"));
+        assert_eq!(
+            REGISTRY_NATIVE_BALANCE.load(&deps.storage).unwrap(),
+            coins(110, "earth")
+        );
+
+        // not expired yet
+        let msg = ExecuteMsg::Refund {
+            id: Some(String::from("deal")),
+        };
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("anybody", &[]),
+            msg.clone(),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::NotExpired {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        // refund returns the remaining native and cw20 tokens to the source
+        env.block.height = 1000;
+        let res = execute(deps.as_mut(), env, mock_info("anybody", &[]), msg).unwrap();
+        assert_eq!(2, res.messages.len());
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "source".into(),
+                amount: coins(110, "earth"),
+            })
+        );
+        assert_eq!(res.messages[1].msg, cw20_transfer("token", "source", 70));
+        let _ = query_details(deps.as_ref(), mock_env(), String::from("deal")).unwrap_err();
+        assert_eq!(REGISTRY_NATIVE_BALANCE.load(&deps.storage).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn top_up_and_details() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            cw20_whitelist: Some(vec![String::from("token")]),
+            ..init_msg_expire_by_height(Some(Expiration::AtHeight(1000)))
+        };
+        let mut env = mock_env();
+        env.block.height = 876;
+        let info = mock_info("creator", &coins(100, "earth"));
+        let contract_addr = env.contract.address.clone();
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        // top up requires funds
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("friend", &[]),
+            ExecuteMsg::TopUp {},
+        )
+        .unwrap_err();
+        match err {
+            ContractError::NoFunds {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        let info = mock_info("friend", &[coin(20, "earth"), coin(5, "moon")]);
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::TopUp {}).unwrap();
+        let info = mock_info("friend", &coins(30, "earth"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::TopUp {}).unwrap();
+        let info = mock_info("token", &[]);
+        execute(deps.as_mut(), env.clone(), info, cw20_deposit(100)).unwrap();
+
+        // registry escrows do not count towards the contract-level balance
+        let info = mock_info("source", &coins(7, "earth"));
+        let msg = create_msg("deal", "alice", "bob");
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        deps.querier
+            .update_balance(&contract_addr, vec![coin(157, "earth"), coin(5, "moon")]);
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Details { id: None }).unwrap();
+        let details: DetailsResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            details,
+            DetailsResponse {
+                arbiter: Addr::unchecked("verifies"),
+                arbiters: vec![Arbiter {
+                    addr: Addr::unchecked("verifies"),
+                    weight: 1,
+                }],
+                threshold: 1,
+                recipient: Addr::unchecked("benefits"),
+                source: Addr::unchecked("creator"),
+                expiration: Some(Expiration::AtHeight(1000)),
+                is_expired: false,
+                cw20_whitelist: vec![Addr::unchecked("token")],
+                native_balance: vec![coin(150, "earth"), coin(5, "moon")],
+                cw20_balance: vec![Cw20CoinVerified {
+                    address: Addr::unchecked("token"),
+                    amount: Uint128::new(100),
+                }],
+            }
+        );
+
+        let res = query_deposits(deps.as_ref(), None, None).unwrap();
+        assert_eq!(
+            res.deposits,
+            vec![
+                DepositInfo {
+                    depositor: Addr::unchecked("creator"),
+                    amount: coins(100, "earth"),
+                },
+                DepositInfo {
+                    depositor: Addr::unchecked("friend"),
+                    amount: vec![coin(50, "earth"), coin(5, "moon")],
+                },
+            ]
+        );
+        let res = query_deposits(deps.as_ref(), Some(String::from("creator")), None).unwrap();
+        assert_eq!(res.deposits.len(), 1);
+
+        // expired escrows cannot be topped up
+        env.block.height = 1000;
+        let info = mock_info("friend", &coins(30, "earth"));
+        let err = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::TopUp {}).unwrap_err();
+        match err {
+            ContractError::Expired { .. } => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+        let res = query(deps.as_ref(), env, QueryMsg::Details { id: None }).unwrap();
+        let details: DetailsResponse = from_binary(&res).unwrap();
+        assert!(details.is_expired);
+    }
+
+    #[test]
+    fn milestones() {
+        let mut deps = mock_dependencies();
+
+        let milestone = |title: &str, amount: u128, deadline: Option<u64>| MilestoneMsg {
+            title: title.to_string(),
+            amount: coins(amount, "earth"),
+            deadline: deadline.map(Expiration::AtHeight),
+        };
+        let mut env = mock_env();
+        env.block.height = 876;
+        let info = mock_info("creator", &coins(1000, "earth"));
+        let contract_addr = env.contract.address.clone();
+
+        // unapproved milestones are refunded after expiration, so there must be one
+        let msg = InstantiateMsg {
+            milestones: Some(vec![milestone("build", 500, Some(900))]),
+            ..init_msg_expire_by_height(None)
+        };
+        let err = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+        match err {
+            ContractError::MilestonesWithoutExpiration {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        // deadlines cannot already be passed
+        let msg = InstantiateMsg {
+            milestones: Some(vec![
+                milestone("design", 200, None),
+                milestone("build", 500, Some(800)),
+            ]),
+            ..init_msg_expire_by_height(Some(Expiration::AtHeight(1000)))
+        };
+        let err = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+        match err {
+            ContractError::MilestoneExpired { index } => assert_eq!(index, 1),
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        let msg = InstantiateMsg {
+            milestones: Some(vec![
+                milestone("design", 200, None),
+                milestone("build", 500, Some(900)),
+                milestone("ship", 300, None),
+            ]),
+            ..init_msg_expire_by_height(Some(Expiration::AtHeight(1000)))
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        deps.querier
+            .update_balance(&contract_addr, coins(1000, "earth"));
+
+        // free-form approvals are disabled
+        let msg = ExecuteMsg::Approve {
+            id: None,
+            quantity: None,
+            cw20_quantity: None,
+        };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("verifies", &[]), msg).unwrap_err();
+        match err {
+            ContractError::MilestonesDefined {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        // only the arbiter can approve a milestone
+        let msg = ExecuteMsg::ApproveMilestone { index: 0 };
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("benefits", &[]),
+            msg.clone(),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("verifies", &[]),
+            msg.clone(),
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "benefits".into(),
+                amount: coins(200, "earth"),
+            })
+        );
+        deps.querier
+            .update_balance(&contract_addr, coins(800, "earth"));
+
+        // a milestone is only released once
+        let err = execute(deps.as_mut(), env.clone(), mock_info("verifies", &[]), msg).unwrap_err();
+        match err {
+            ContractError::MilestoneReleased { index } => assert_eq!(index, 0),
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        let msg = ExecuteMsg::ApproveMilestone { index: 3 };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("verifies", &[]), msg).unwrap_err();
+        match err {
+            ContractError::MilestoneNotFound { index } => assert_eq!(index, 3),
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        // the build milestone misses its deadline
+        env.block.height = 950;
+        let msg = ExecuteMsg::ApproveMilestone { index: 1 };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("verifies", &[]), msg).unwrap_err();
+        match err {
+            ContractError::MilestoneExpired { index } => assert_eq!(index, 1),
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        let msg = ExecuteMsg::ApproveMilestone { index: 2 };
+        execute(deps.as_mut(), env.clone(), mock_info("verifies", &[]), msg).unwrap();
+        deps.querier
+            .update_balance(&contract_addr, coins(500, "earth"));
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Milestones {}).unwrap();
+        let res: MilestonesResponse = from_binary(&res).unwrap();
+        let statuses: Vec<_> = res
+            .milestones
+            .iter()
+            .map(|m| (m.status.clone(), m.released_at))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                (MilestoneStatus::Released, Some(876)),
+                (MilestoneStatus::Expired, None),
+                (MilestoneStatus::Released, Some(950)),
+            ]
+        );
+
+        // after expiration only the unapproved milestone funds are refunded
+        env.block.height = 1000;
+        let res = execute(
+            deps.as_mut(),
+            env,
+            mock_info("anybody", &[]),
+            ExecuteMsg::Refund { id: None },
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(500, "earth"),
+            })
+        );
+    }
+
+    #[test]
+    fn multi_arbiter_threshold() {
+        let mut deps = mock_dependencies();
+
+        let arbiter = |addr: &str, weight: u64| ArbiterMsg {
+            addr: addr.to_string(),
+            weight,
+        };
+        let mut env = mock_env();
+        env.block.height = 876;
+        let info = mock_info("creator", &coins(1000, "earth"));
+        let contract_addr = env.contract.address.clone();
+
+        // the arbiter must be one of the arbiters
+        let msg = InstantiateMsg {
+            arbiters: Some(vec![arbiter("alice", 1), arbiter("bob", 1)]),
+            ..init_msg_expire_by_height(None)
+        };
+        let err = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+        match err {
+            ContractError::ArbiterNotInArbiters {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        let msg = InstantiateMsg {
+            arbiter: String::from("alice"),
+            arbiters: Some(vec![arbiter("alice", 1), arbiter("bob", 1)]),
+            threshold: Some(3),
+            ..init_msg_expire_by_height(None)
+        };
+        let err = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+        match err {
+            ContractError::InvalidThreshold {
+                threshold,
+                total_weight,
+            } => assert_eq!((threshold, total_weight), (3, 2)),
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        let msg = InstantiateMsg {
+            arbiters: Some(vec![arbiter("alice", 1), arbiter("alice", 1)]),
+            ..init_msg_expire_by_height(None)
+        };
+        let err = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+        match err {
+            ContractError::InvalidArbiters {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        let msg = InstantiateMsg {
+            arbiter: String::from("alice"),
+            arbiters: Some(vec![
+                arbiter("alice", 1),
+                arbiter("bob", 1),
+                arbiter("carol", 2),
+            ]),
+            threshold: Some(3),
+            ..init_msg_expire_by_height(None)
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        deps.querier
+            .update_balance(&contract_addr, coins(1000, "earth"));
+        let details = query_config_details(deps.as_ref(), env.clone()).unwrap();
+        assert_eq!(
+            details.arbiters,
+            vec![
+                Arbiter {
+                    addr: Addr::unchecked("alice"),
+                    weight: 1,
+                },
+                Arbiter {
+                    addr: Addr::unchecked("bob"),
+                    weight: 1,
+                },
+                Arbiter {
+                    addr: Addr::unchecked("carol"),
+                    weight: 2,
+                },
+            ]
+        );
+        assert_eq!(details.threshold, 3);
+
+        // only the arbiters approve
+        let release = ExecuteMsg::Approve {
+            id: None,
+            quantity: Some(coins(400, "earth")),
+            cw20_quantity: None,
+        };
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("verifies", &[]),
+            release.clone(),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        // the first approval only records the vote
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("alice", &[]),
+            release.clone(),
+        )
+        .unwrap();
+        assert_eq!(0, res.messages.len());
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("alice", &[]),
+            release.clone(),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::AlreadyVoted {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        // approving another amount is a separate release
+        let other = ExecuteMsg::Approve {
+            id: None,
+            quantity: Some(coins(500, "earth")),
+            cw20_quantity: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), other).unwrap();
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::PendingReleases {}).unwrap();
+        let res: PendingReleasesResponse = from_binary(&res).unwrap();
+        assert_eq!(res.releases.len(), 2);
+        let pending = res
+            .releases
+            .iter()
+            .find(|r| r.signers == vec![Addr::unchecked("alice")])
+            .unwrap();
+        assert_eq!(
+            pending.release,
+            Release::Approve {
+                recipient: Addr::unchecked("benefits"),
+                quantity: Some(coins(400, "earth")),
+                cw20_quantity: None,
+            }
+        );
+        assert_eq!(pending.weight, 1);
+
+        // reaching the threshold sends the release
+        let res = execute(deps.as_mut(), env.clone(), mock_info("carol", &[]), release).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "benefits".into(),
+                amount: coins(400, "earth"),
+            })
+        );
+        let res = query_pending_releases(deps.as_ref()).unwrap();
+        assert_eq!(res.releases.len(), 1);
+        assert_eq!(res.releases[0].signers, vec![Addr::unchecked("bob")]);
+    }
+
+    #[test]
+    fn dispute_and_resolve() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            cw20_whitelist: Some(vec![String::from("token")]),
+            ..init_msg_expire_by_height(Some(Expiration::AtHeight(1000)))
+        };
+        let mut env = mock_env();
+        env.block.height = 876;
+        let info = mock_info("creator", &coins(1001, "earth"));
+        let contract_addr = env.contract.address.clone();
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        deps.querier
+            .update_balance(&contract_addr, coins(1001, "earth"));
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("token", &[]),
+            cw20_deposit(100),
+        )
+        .unwrap();
+
+        // nothing to resolve yet
+        let resolve = ExecuteMsg::Resolve {
+            recipient_share: Decimal::percent(30),
+        };
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("verifies", &[]),
+            resolve.clone(),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::NotDisputed {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        // only the parties can dispute
+        let dispute = ExecuteMsg::Dispute {
+            reason: String::from("work not delivered"),
+        };
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("verifies", &[]),
+            dispute.clone(),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("creator", &[]),
+            dispute,
+        )
+        .unwrap();
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Dispute {}).unwrap();
+        let res: DisputeResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            res.dispute,
+            Some(Dispute {
+                raised_by: Addr::unchecked("creator"),
+                reason: String::from("work not delivered"),
+                raised_at: 876,
+            })
+        );
+
+        // normal flows are blocked, even after expiration
+        let approve = ExecuteMsg::Approve {
+            id: None,
+            quantity: None,
+            cw20_quantity: None,
+        };
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("verifies", &[]),
+            approve,
+        )
+        .unwrap_err();
+        match err {
+            ContractError::Disputed {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+        env.block.height = 1000;
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("anybody", &[]),
+            ExecuteMsg::Refund { id: None },
+        )
+        .unwrap_err();
+        match err {
+            ContractError::Disputed {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("verifies", &[]),
+            ExecuteMsg::Resolve {
+                recipient_share: Decimal::percent(101),
+            },
+        )
+        .unwrap_err();
+        match err {
+            ContractError::InvalidShare { .. } => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        // the arbiter splits every held token, rounding down the recipient share
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("verifies", &[]),
+            resolve,
+        )
+        .unwrap();
+        assert_eq!(4, res.messages.len());
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "benefits".into(),
+                amount: coins(300, "earth"),
+            })
+        );
+        assert_eq!(res.messages[1].msg, cw20_transfer("token", "benefits", 30));
+        assert_eq!(
+            res.messages[2].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(701, "earth"),
+            })
+        );
+        assert_eq!(res.messages[3].msg, cw20_transfer("token", "creator", 70));
+
+        let res = query(deps.as_ref(), env, QueryMsg::Dispute {}).unwrap();
+        let res: DisputeResponse = from_binary(&res).unwrap();
+        assert_eq!(res.dispute, None);
+    }
+
+    #[test]
+    fn vesting() {
+        let mut deps = mock_dependencies();
+
+        let mut env = mock_env();
+        let start = env.block.time;
+        let vesting = VestingMsg {
+            amount: coins(1000, "earth"),
+            start: None,
+            cliff: Some(start.plus_seconds(100)),
+            end: start.plus_seconds(1000),
+        };
+
+        // schedule must be ordered and cannot be combined with an expiration
+        let msg = InstantiateMsg {
+            vesting: Some(VestingMsg {
+                cliff: Some(start.plus_seconds(2000)),
+                ..vesting.clone()
+            }),
+            ..init_msg_expire_by_height(None)
+        };
+        let info = mock_info("creator", &coins(1000, "earth"));
+        let err = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+        match err {
+            ContractError::InvalidVesting {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+        let msg = InstantiateMsg {
+            vesting: Some(vesting.clone()),
+            ..init_msg_expire_by_height(Some(Expiration::AtHeight(100000)))
+        };
+        let err = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+        match err {
+            ContractError::InvalidVesting {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        let msg = InstantiateMsg {
+            vesting: Some(vesting),
+            ..init_msg_expire_by_height(None)
+        };
+        let contract_addr = env.contract.address.clone();
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        deps.querier
+            .update_balance(&contract_addr, coins(1000, "earth"));
+
+        // nothing is vested before the cliff
+        env.block.time = start.plus_seconds(50);
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("benefits", &[]),
+            ExecuteMsg::Claim { preimage: None },
+        )
+        .unwrap_err();
+        match err {
+            ContractError::NothingToClaim {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        // funds are only released through Claim
+        let approve = ExecuteMsg::Approve {
+            id: None,
+            quantity: None,
+            cw20_quantity: None,
+        };
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("verifies", &[]),
+            approve,
+        )
+        .unwrap_err();
+        match err {
+            ContractError::VestingDefined {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        // after the cliff the linear amount since start is claimable
+        env.block.time = start.plus_seconds(250);
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("anybody", &[]),
+            ExecuteMsg::Claim { preimage: None },
+        )
+        .unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("benefits", &[]),
+            ExecuteMsg::Claim { preimage: None },
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "benefits".into(),
+                amount: coins(250, "earth"),
+            })
+        );
+        deps.querier
+            .update_balance(&contract_addr, coins(750, "earth"));
+
+        // cancelling keeps the vested part claimable and refunds the rest
+        env.block.time = start.plus_seconds(400);
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("verifies", &[]),
+            ExecuteMsg::CancelVesting {},
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(600, "earth"),
+            })
+        );
+        deps.querier
+            .update_balance(&contract_addr, coins(150, "earth"));
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("verifies", &[]),
+            ExecuteMsg::CancelVesting {},
+        )
+        .unwrap_err();
+        match err {
+            ContractError::VestingCancelled {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        env.block.time = start.plus_seconds(900);
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Vesting {}).unwrap();
+        let res: VestingResponse = from_binary(&res).unwrap();
+        assert_eq!(res.start, start);
+        assert_eq!(res.cancelled_at, Some(start.plus_seconds(400)));
+        assert_eq!(res.vested, coins(400, "earth"));
+        assert_eq!(res.claimed, coins(250, "earth"));
+        assert_eq!(res.claimable, coins(150, "earth"));
+
+        let res = execute(
+            deps.as_mut(),
+            env,
+            mock_info("benefits", &[]),
+            ExecuteMsg::Claim { preimage: None },
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "benefits".into(),
+                amount: coins(150, "earth"),
+            })
+        );
+    }
+
+    #[test]
+    fn update_parties() {
+        let mut deps = mock_dependencies();
+
+        let arbiter = |addr: &str, weight: u64| ArbiterMsg {
+            addr: addr.to_string(),
+            weight,
+        };
+        let msg = InstantiateMsg {
+            arbiters: Some(vec![arbiter("verifies", 1), arbiter("bob", 1)]),
+            ..init_msg_expire_by_height(None)
+        };
+        let mut env = mock_env();
+        env.block.height = 876;
+        let info = mock_info("creator", &coins(1000, "earth"));
+        let contract_addr = env.contract.address.clone();
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        deps.querier
+            .update_balance(&contract_addr, coins(1000, "earth"));
+
+        let release = ExecuteMsg::Approve {
+            id: None,
+            quantity: Some(coins(400, "earth")),
+            cw20_quantity: None,
+        };
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("verifies", &[]),
+            release.clone(),
+        )
+        .unwrap();
+
+        // only the arbiter or the source can propose a handover
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("benefits", &[]),
+            ExecuteMsg::UpdateArbiter {
+                arbiter: String::from("successor"),
+            },
+        )
+        .unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("verifies", &[]),
+            ExecuteMsg::AcceptArbiter {
+                arbiter: String::from("successor"),
+            },
+        )
+        .unwrap_err();
+        match err {
+            ContractError::NoProposal {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        // a newer proposal replaces the one the arbiter is accepting
+        for arbiter in ["intern", "successor"] {
+            execute(
+                deps.as_mut(),
+                env.clone(),
+                mock_info("creator", &[]),
+                ExecuteMsg::UpdateArbiter {
+                    arbiter: String::from(arbiter),
+                },
+            )
+            .unwrap();
+        }
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("verifies", &[]),
+            ExecuteMsg::AcceptArbiter {
+                arbiter: String::from("intern"),
+            },
+        )
+        .unwrap_err();
+        match err {
+            ContractError::StaleProposal { proposed } => assert_eq!(proposed, "successor"),
+            e => panic!("unexpected error: {:?}", e),
+        }
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("creator", &[]),
+            ExecuteMsg::AcceptArbiter {
+                arbiter: String::from("successor"),
+            },
+        )
+        .unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::ArbiterProposal {}).unwrap();
+        let res: ArbiterProposalResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            res.proposal,
+            Some(ArbiterProposal {
+                arbiter: Addr::unchecked("successor"),
+                proposed_by: Addr::unchecked("creator"),
+            })
+        );
+
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("verifies", &[]),
+            ExecuteMsg::AcceptArbiter {
+                arbiter: String::from("successor"),
+            },
+        )
+        .unwrap();
+        assert_eq!(res.attributes[0], ("action", "update_arbiter"));
+        assert_eq!(res.attributes[1], ("previous", "verifies"));
+        assert_eq!(res.attributes[2], ("arbiter", "successor"));
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Arbiter {}).unwrap();
+        let res: ArbiterResponse = from_binary(&res).unwrap();
+        assert_eq!(res.arbiter, Addr::unchecked("successor"));
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::ArbiterProposal {}).unwrap();
+        let res: ArbiterProposalResponse = from_binary(&res).unwrap();
+        assert_eq!(res.proposal, None);
+
+        // the successor inherits the approval of the previous arbiter
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("verifies", &[]),
+            release.clone(),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("successor", &[]),
+            release.clone(),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::AlreadyVoted {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        // redirecting the payout drops the approvals for the previous recipient
+        let update_recipient = ExecuteMsg::UpdateRecipient {
+            recipient: String::from("heir"),
+        };
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("creator", &[]),
+            update_recipient.clone(),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("benefits", &[]),
+            update_recipient,
+        )
+        .unwrap();
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::PendingReleases {}).unwrap();
+        let res: PendingReleasesResponse = from_binary(&res).unwrap();
+        assert!(res.releases.is_empty());
+
+        for arbiter in ["successor", "bob"] {
+            let res = execute(
+                deps.as_mut(),
+                env.clone(),
+                mock_info(arbiter, &[]),
+                release.clone(),
+            )
+            .unwrap();
+            if arbiter == "bob" {
+                assert_eq!(
+                    res.messages[0].msg,
+                    CosmosMsg::Bank(BankMsg::Send {
+                        to_address: "heir".into(),
+                        amount: coins(400, "earth"),
+                    })
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn migrate_config() {
+        use crate::state::CONFIG_KEY;
+        use cw_storage_plus::Item;
+
+        // layout written by 0.11
+        #[cosmwasm_schema::cw_serde]
+        struct LegacyConfig {
+            arbiter: Addr,
+            recipient: Addr,
+            source: Addr,
+            expiration: Option<Expiration>,
+        }
+
+        let mut deps = mock_dependencies();
+        let legacy = LegacyConfig {
+            arbiter: Addr::unchecked("verifies"),
+            recipient: Addr::unchecked("benefits"),
+            source: Addr::unchecked("creator"),
+            expiration: Some(Expiration::AtHeight(1000)),
+        };
+        Item::new(CONFIG_KEY)
+            .save(deps.as_mut().storage, &legacy)
+            .unwrap();
+        set_contract_version(deps.as_mut().storage, LEGACY_CONTRACT_NAME, "0.11.0").unwrap();
+        assert!(CONFIG.load(deps.as_ref().storage).is_err());
+
+        let res = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+        assert_eq!(res.attributes[1], ("from_version", "0.11.0"));
+        assert_eq!(
+            CONFIG.load(deps.as_ref().storage).unwrap(),
+            Config {
+                arbiter: Addr::unchecked("verifies"),
+                recipient: Addr::unchecked("benefits"),
+                source: Addr::unchecked("creator"),
+                expiration: Some(Expiration::AtHeight(1000)),
+                cw20_whitelist: vec![],
+                arbiters: vec![Arbiter {
+                    addr: Addr::unchecked("verifies"),
+                    weight: 1,
+                }],
+                threshold: 1,
+            }
+        );
+        let version = get_contract_version(deps.as_ref().storage).unwrap();
+        assert_eq!(version.contract, CONTRACT_NAME);
+        assert_eq!(version.version, CONTRACT_VERSION);
+
+        // the escrow keeps working with the upgraded config
         let mut env = mock_env();
-        env.block.height = 1100;
-        env.block.time = Timestamp::from_seconds(0);
-        let info = mock_info("verifies", &[]);
-        let execute_res = execute(deps.as_mut(), env, info, msg.clone());
-        match execute_res.unwrap_err() {
-            ContractError::Expired { .. } => {}
+        env.block.height = 876;
+        deps.querier
+            .update_balance(&env.contract.address, coins(1000, "earth"));
+        let res = execute(
+            deps.as_mut(),
+            env,
+            mock_info("verifies", &[]),
+            ExecuteMsg::Approve {
+                id: None,
+                quantity: None,
+                cw20_quantity: None,
+            },
+        )
+        .unwrap();
+        assert_eq!(1, res.messages.len());
+
+        // migrating the current layout again leaves it untouched
+        let config = CONFIG.load(deps.as_ref().storage).unwrap();
+        migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+        assert_eq!(CONFIG.load(deps.as_ref().storage).unwrap(), config);
+
+        // refuses downgrades and other contracts
+        set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "99.0.0").unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+        match err {
+            ContractError::CannotMigrateVersion { version } => assert_eq!(version, "99.0.0"),
+            e => panic!("unexpected error: {:?}", e),
+        }
+        set_contract_version(deps.as_mut().storage, "crates.io:cw20-base", "0.11.0").unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+        match err {
+            ContractError::CannotMigrate { contract } => {
+                assert_eq!(contract, "crates.io:cw20-base")
+            }
             e => panic!("unexpected error: {:?}", e),
         }
+    }
 
-        // complete release by verifier, before expiration
+    #[test]
+    fn release_condition() {
+        let mut deps = mock_dependencies();
+
+        let condition = ReleaseConditionMsg {
+            contract: String::from("oracle"),
+            query: to_binary(&"outcome").unwrap(),
+            expected: to_binary(&"yes").unwrap(),
+        };
+        let msg = InstantiateMsg {
+            release_condition: Some(condition.clone()),
+            milestones: Some(vec![MilestoneMsg {
+                title: String::from("design"),
+                amount: coins(100, "earth"),
+                deadline: None,
+            }]),
+            ..init_msg_expire_by_height(None)
+        };
         let mut env = mock_env();
+        env.block.height = 876;
+        let info = mock_info("creator", &coins(1000, "earth"));
+        let err = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+        match err {
+            ContractError::InvalidReleaseCondition {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        let msg = InstantiateMsg {
+            release_condition: Some(condition),
+            ..init_msg_expire_by_height(Some(Expiration::AtHeight(1000)))
+        };
+        let contract_addr = env.contract.address.clone();
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        deps.querier
+            .update_balance(&contract_addr, coins(1000, "earth"));
+
+        let oracle = |outcome: &'static str| {
+            move |query: &WasmQuery| match query {
+                WasmQuery::Smart { contract_addr, msg } if contract_addr == "oracle" => {
+                    assert_eq!(msg, &to_binary(&"outcome").unwrap());
+                    SystemResult::Ok(ContractResult::Ok(to_binary(&outcome).unwrap()))
+                }
+                _ => panic!("unexpected query: {:?}", query),
+            }
+        };
+
+        // anyone can try, but nothing is released until the oracle agrees
+        deps.querier.update_wasm(oracle("no"));
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("anybody", &[]),
+            ExecuteMsg::ReleaseIfConditionMet {},
+        )
+        .unwrap_err();
+        match err {
+            ContractError::ConditionNotMet {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::ReleaseCondition {}).unwrap();
+        let res: ReleaseConditionResponse = from_binary(&res).unwrap();
+        assert!(!res.is_met);
+        assert_eq!(res.condition.unwrap().contract, Addr::unchecked("oracle"));
+
+        deps.querier.update_wasm(oracle("yes"));
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::ReleaseCondition {}).unwrap();
+        let res: ReleaseConditionResponse = from_binary(&res).unwrap();
+        assert!(res.is_met);
+
+        // not after expiration though
+        env.block.height = 1000;
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("anybody", &[]),
+            ExecuteMsg::ReleaseIfConditionMet {},
+        )
+        .unwrap_err();
+        match err {
+            ContractError::Expired { .. } => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
         env.block.height = 999;
-        env.block.time = Timestamp::from_seconds(0);
-        let info = mock_info("verifies", &[]);
-        let execute_res = execute(deps.as_mut(), env, info, msg).unwrap();
-        assert_eq!(1, execute_res.messages.len());
-        let msg = execute_res.messages.get(0).expect("no message");
+        let res = execute(
+            deps.as_mut(),
+            env,
+            mock_info("anybody", &[]),
+            ExecuteMsg::ReleaseIfConditionMet {},
+        )
+        .unwrap();
+        assert_eq!(res.attributes[0], ("action", "release_if_condition_met"));
         assert_eq!(
-            msg.msg,
+            res.messages[0].msg,
             CosmosMsg::Bank(BankMsg::Send {
                 to_address: "benefits".into(),
                 amount: coins(1000, "earth"),
             })
         );
+    }
 
-        // partial release by verifier, before expiration
-        let partial_msg = ExecuteMsg::Approve {
-            quantity: Some(coins(500, "earth")),
+    #[test]
+    fn hashlock() {
+        let mut deps = mock_dependencies();
+
+        let secret = b"atomic swap secret";
+        let hash = hex::encode(Sha256::digest(secret));
+        let msg = InstantiateMsg {
+            hashlock: Some(hash.clone()),
+            ..init_msg_expire_by_height(None)
         };
         let mut env = mock_env();
+        env.block.height = 876;
+        let info = mock_info("creator", &coins(1000, "earth"));
+        let err = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+        match err {
+            ContractError::InvalidHashlock {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+        let msg = InstantiateMsg {
+            hashlock: Some(String::from("not a hash")),
+            ..init_msg_expire_by_height(Some(Expiration::AtHeight(1000)))
+        };
+        let err = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+        match err {
+            ContractError::InvalidHashlock {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        let msg = InstantiateMsg {
+            hashlock: Some(hash.to_uppercase()),
+            cw20_whitelist: Some(vec![String::from("token")]),
+            ..init_msg_expire_by_height(Some(Expiration::AtHeight(1000)))
+        };
+        let contract_addr = env.contract.address.clone();
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        deps.querier
+            .update_balance(&contract_addr, coins(1000, "earth"));
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("token", &[]),
+            cw20_deposit(100),
+        )
+        .unwrap();
+
+        // the arbiter cannot release a hash-time-locked escrow
+        let approve = ExecuteMsg::Approve {
+            id: None,
+            quantity: None,
+            cw20_quantity: None,
+        };
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("verifies", &[]),
+            approve,
+        )
+        .unwrap_err();
+        match err {
+            ContractError::HashlockDefined {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        for preimage in [None, Some(hex::encode(b"wrong secret"))] {
+            let err = execute(
+                deps.as_mut(),
+                env.clone(),
+                mock_info("anybody", &[]),
+                ExecuteMsg::Claim { preimage },
+            )
+            .unwrap_err();
+            match err {
+                ContractError::InvalidPreimage {} => {}
+                e => panic!("unexpected error: {:?}", e),
+            }
+        }
+
+        // neither party can dispute it to block the claim
+        for sender in ["benefits", "creator"] {
+            let msg = ExecuteMsg::Dispute {
+                reason: String::from("no"),
+            };
+            let err = execute(deps.as_mut(), env.clone(), mock_info(sender, &[]), msg).unwrap_err();
+            match err {
+                ContractError::HashlockDefined {} => {}
+                e => panic!("unexpected error: {:?}", e),
+            }
+        }
+
+        // the preimage only works before expiration
+        let claim = ExecuteMsg::Claim {
+            preimage: Some(hex::encode(secret)),
+        };
+        env.block.height = 1000;
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("anybody", &[]),
+            claim.clone(),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::Expired { .. } => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        // anyone presenting it releases everything to the recipient
         env.block.height = 999;
-        env.block.time = Timestamp::from_seconds(0);
-        let info = mock_info("verifies", &[]);
-        let execute_res = execute(deps.as_mut(), env, info, partial_msg).unwrap();
-        assert_eq!(1, execute_res.messages.len());
-        let msg = execute_res.messages.get(0).expect("no message");
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("anybody", &[]),
+            claim.clone(),
+        )
+        .unwrap();
+        assert_eq!(2, res.messages.len());
         assert_eq!(
-            msg.msg,
+            res.messages[0].msg,
             CosmosMsg::Bank(BankMsg::Send {
                 to_address: "benefits".into(),
-                amount: coins(500, "earth"),
+                amount: coins(1000, "earth"),
+            })
+        );
+        assert_eq!(res.messages[1].msg, cw20_transfer("token", "benefits", 100));
+        assert_eq!(res.attributes[2], ("preimage", hex::encode(secret)));
+
+        // and reveals the secret to the other side of the swap
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Hashlock {}).unwrap();
+        let res: HashlockResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            res.hashlock,
+            Some(Hashlock {
+                hash,
+                preimage: Some(hex::encode(secret)),
             })
         );
+        let err = execute(deps.as_mut(), env, mock_info("anybody", &[]), claim).unwrap_err();
+        match err {
+            ContractError::NothingToClaim {} => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
     }
 
     #[test]
-    fn handle_refund() {
+    fn fee_on_approve() {
         let mut deps = mock_dependencies();
 
-        // initialize the store
-        let init_amount = coins(1000, "earth");
-        let msg = init_msg_expire_by_height(Some(Expiration::AtHeight(1000)));
+        let fee = |rate: Decimal| FeeMsg {
+            collector: String::from("collector"),
+            rate,
+        };
+        let msg = InstantiateMsg {
+            fee: Some(fee(Decimal::one())),
+            ..init_msg_expire_by_height(Some(Expiration::AtHeight(1000)))
+        };
         let mut env = mock_env();
         env.block.height = 876;
-        env.block.time = Timestamp::from_seconds(0);
-        let info = mock_info("creator", &init_amount);
-        let contract_addr = env.clone().contract.address;
-        let init_res = instantiate(deps.as_mut(), env, info, msg).unwrap();
-        assert_eq!(0, init_res.messages.len());
-
-        // balance changed in init
-        deps.querier.update_balance(&contract_addr, init_amount);
-
-        // cannot release when unexpired (height < Expiration::AtHeight(1000))
-        let msg = ExecuteMsg::Refund {};
-        let mut env = mock_env();
-        env.block.height = 800;
-        env.block.time = Timestamp::from_seconds(0);
-        let info = mock_info("anybody", &[]);
-        let execute_res = execute(deps.as_mut(), env, info, msg);
-        match execute_res.unwrap_err() {
-            ContractError::NotExpired { .. } => {}
+        let info = mock_info("creator", &coins(1000, "earth"));
+        let err = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+        match err {
+            ContractError::InvalidFeeRate { rate } => assert_eq!(rate, Decimal::one()),
             e => panic!("unexpected error: {:?}", e),
         }
 
-        // Contract expires when height == Expiration::AtHeight(1000)
-        let msg = ExecuteMsg::Refund {};
-        let mut env = mock_env();
+        let msg = InstantiateMsg {
+            fee: Some(fee(Decimal::permille(25))),
+            cw20_whitelist: Some(vec![String::from("token")]),
+            ..init_msg_expire_by_height(Some(Expiration::AtHeight(1000)))
+        };
+        let contract_addr = env.contract.address.clone();
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        deps.querier
+            .update_balance(&contract_addr, coins(1000, "earth"));
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("token", &[]),
+            cw20_deposit(100),
+        )
+        .unwrap();
+
+        // 2.5% of every released token goes to the collector, rounded down
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("verifies", &[]),
+            ExecuteMsg::Approve {
+                id: None,
+                quantity: Some(coins(333, "earth")),
+                cw20_quantity: Some(vec![Cw20Coin {
+                    address: String::from("token"),
+                    amount: Uint128::new(100),
+                }]),
+            },
+        )
+        .unwrap();
+        assert_eq!(4, res.messages.len());
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "benefits".into(),
+                amount: coins(325, "earth"),
+            })
+        );
+        assert_eq!(res.messages[1].msg, cw20_transfer("token", "benefits", 98));
+        assert_eq!(
+            res.messages[2].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "collector".into(),
+                amount: coins(8, "earth"),
+            })
+        );
+        assert_eq!(res.messages[3].msg, cw20_transfer("token", "collector", 2));
+        deps.querier
+            .update_balance(&contract_addr, coins(667, "earth"));
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Fee {}).unwrap();
+        let res: FeeResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            res.fee,
+            Some(Fee {
+                collector: Addr::unchecked("collector"),
+                rate: Decimal::permille(25),
+            })
+        );
+        assert_eq!(res.collected.native, coins(8, "earth"));
+        assert_eq!(
+            res.collected.cw20,
+            vec![Cw20CoinVerified {
+                address: Addr::unchecked("token"),
+                amount: Uint128::new(2),
+            }]
+        );
+
+        // refunds are exempt
         env.block.height = 1000;
-        env.block.time = Timestamp::from_seconds(0);
-        let info = mock_info("anybody", &[]);
-        let execute_res = execute(deps.as_mut(), env, info, msg).unwrap();
-        assert_eq!(1, execute_res.messages.len());
-        let msg = execute_res.messages.get(0).expect("no message");
+        let res = execute(
+            deps.as_mut(),
+            env,
+            mock_info("anybody", &[]),
+            ExecuteMsg::Refund { id: None },
+        )
+        .unwrap();
+        assert_eq!(1, res.messages.len());
         assert_eq!(
-            msg.msg,
+            res.messages[0].msg,
             CosmosMsg::Bank(BankMsg::Send {
                 to_address: "creator".into(),
-                amount: coins(1000, "earth"),
+                amount: coins(667, "earth"),
             })
         );
+    }
 
-        // anyone can release after expiration
-        let msg = ExecuteMsg::Refund {};
+    #[test]
+    fn fee_on_every_release() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            fee: Some(FeeMsg {
+                collector: String::from("collector"),
+                rate: Decimal::percent(10),
+            }),
+            milestones: Some(vec![MilestoneMsg {
+                title: String::from("design"),
+                amount: coins(200, "earth"),
+                deadline: None,
+            }]),
+            ..init_msg_expire_by_height(Some(Expiration::AtHeight(1000)))
+        };
         let mut env = mock_env();
-        env.block.height = 1001;
-        env.block.time = Timestamp::from_seconds(0);
-        let info = mock_info("anybody", &[]);
-        let execute_res = execute(deps.as_mut(), env, info, msg).unwrap();
-        assert_eq!(1, execute_res.messages.len());
-        let msg = execute_res.messages.get(0).expect("no message");
+        env.block.height = 876;
+        let contract_addr = env.contract.address.clone();
+        let info = mock_info("creator", &coins(1000, "earth"));
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let info = mock_info("source", &coins(100, "earth"));
+        let msg = create_msg("deal-1", "alice", "bob");
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        deps.querier
+            .update_balance(&contract_addr, coins(1100, "earth"));
+
+        // registry escrows pay the fee too
+        let msg = ExecuteMsg::Approve {
+            id: Some(String::from("deal-1")),
+            quantity: None,
+            cw20_quantity: None,
+        };
+        let res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        assert_eq!(2, res.messages.len());
         assert_eq!(
-            msg.msg,
+            res.messages[0].msg,
             CosmosMsg::Bank(BankMsg::Send {
-                to_address: "creator".into(),
-                amount: coins(1000, "earth"),
+                to_address: "bob".into(),
+                amount: coins(90, "earth"),
+            })
+        );
+        assert_eq!(
+            res.messages[1].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "collector".into(),
+                amount: coins(10, "earth"),
+            })
+        );
+        deps.querier
+            .update_balance(&contract_addr, coins(1000, "earth"));
+
+        // and so do milestones
+        let msg = ExecuteMsg::ApproveMilestone { index: 0 };
+        let res = execute(deps.as_mut(), env.clone(), mock_info("verifies", &[]), msg).unwrap();
+        assert_eq!(2, res.messages.len());
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "benefits".into(),
+                amount: coins(180, "earth"),
+            })
+        );
+        assert_eq!(
+            res.messages[1].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "collector".into(),
+                amount: coins(20, "earth"),
             })
         );
+
+        let res = query(deps.as_ref(), env, QueryMsg::Fee {}).unwrap();
+        let res: FeeResponse = from_binary(&res).unwrap();
+        assert_eq!(res.collected.native, coins(30, "earth"));
+        assert!(res.collected.cw20.is_empty());
     }
 
     #[test]
-    fn handle_refund_no_expiration() {
+    fn refund_after_release_and_top_up() {
         let mut deps = mock_dependencies();
 
-        // initialize the store
-        let init_amount = coins(1000, "earth");
-        let msg = init_msg_expire_by_height(None);
+        let msg = init_msg_expire_by_height(Some(Expiration::AtHeight(1000)));
         let mut env = mock_env();
         env.block.height = 876;
-        env.block.time = Timestamp::from_seconds(0);
-        let info = mock_info("creator", &init_amount);
-        let contract_addr = env.clone().contract.address;
-        let init_res = instantiate(deps.as_mut(), env, info, msg).unwrap();
-        assert_eq!(0, init_res.messages.len());
+        let contract_addr = env.contract.address.clone();
+        let info = mock_info("creator", &coins(100, "earth"));
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let info = mock_info("friend", &coins(50, "earth"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::TopUp {}).unwrap();
+        deps.querier
+            .update_balance(&contract_addr, coins(150, "earth"));
 
-        // balance changed in init
-        deps.querier.update_balance(&contract_addr, init_amount);
+        // a partial release is paid out of both deposits in proportion
+        let approve = |quantity: Option<Vec<Coin>>| ExecuteMsg::Approve {
+            id: None,
+            quantity,
+            cw20_quantity: None,
+        };
+        let info = mock_info("verifies", &[]);
+        let msg = approve(Some(coins(60, "earth")));
+        execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        deps.querier
+            .update_balance(&contract_addr, coins(90, "earth"));
+        let res = query_deposits(deps.as_ref(), None, None).unwrap();
+        assert_eq!(
+            res.deposits,
+            vec![
+                DepositInfo {
+                    depositor: Addr::unchecked("creator"),
+                    amount: coins(60, "earth"),
+                },
+                DepositInfo {
+                    depositor: Addr::unchecked("friend"),
+                    amount: coins(30, "earth"),
+                },
+            ]
+        );
 
-        // cannot release when unexpired (no expiration)
-        let msg = ExecuteMsg::Refund {};
-        let mut env = mock_env();
-        env.block.height = 800;
-        env.block.time = Timestamp::from_seconds(0);
-        let info = mock_info("anybody", &[]);
-        let execute_res = execute(deps.as_mut(), env, info, msg);
-        match execute_res.unwrap_err() {
-            ContractError::NotExpired { .. } => {}
-            e => panic!("unexpected error: {:?}", e),
-        }
+        // releasing the rest settles them
+        execute(deps.as_mut(), env.clone(), info, approve(None)).unwrap();
+        deps.querier.update_balance(&contract_addr, vec![]);
+        let res = query_deposits(deps.as_ref(), None, None).unwrap();
+        assert!(res.deposits.is_empty());
+
+        // so a later depositor gets their whole top up back
+        let info = mock_info("late", &coins(30, "earth"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::TopUp {}).unwrap();
+        deps.querier
+            .update_balance(&contract_addr, coins(30, "earth"));
+        env.block.height = 1000;
+        let msg = ExecuteMsg::Refund { id: None };
+        let res = execute(deps.as_mut(), env, mock_info("anybody", &[]), msg).unwrap();
+        assert_eq!(1, res.messages.len());
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "late".into(),
+                amount: coins(30, "earth"),
+            })
+        );
     }
 }
@@ -1,4 +1,4 @@
-use cosmwasm_std::StdError;
+use cosmwasm_std::{Decimal, StdError};
 use cw_utils::Expiration;
 use thiserror::Error;
 
@@ -7,6 +7,9 @@ pub enum ContractError {
     #[error("{0}")]
     Std(#[from] StdError),
 
+    #[error("Semver parsing error: {0}")]
+    SemVer(String),
+
     #[error("Unauthorized")]
     Unauthorized {},
 
@@ -15,4 +18,112 @@ pub enum ContractError {
 
     #[error("Escrow not expired")]
     NotExpired {},
+
+    #[error("Token is not in the cw20 whitelist (token: {token})")]
+    NotInWhitelist { token: String },
+
+    #[error("Insufficient escrow balance (token: {token})")]
+    InsufficientBalance { token: String },
+
+    #[error("Escrow releases funds by milestone, use ApproveMilestone")]
+    MilestonesDefined {},
+
+    #[error("Escrow has no milestones")]
+    NoMilestones {},
+
+    #[error("Milestones need an expiration to refund the unapproved ones")]
+    MilestonesWithoutExpiration {},
+
+    #[error("Milestone not found (index: {index})")]
+    MilestoneNotFound { index: u32 },
+
+    #[error("Milestone already released (index: {index})")]
+    MilestoneReleased { index: u32 },
+
+    #[error("Milestone deadline passed (index: {index})")]
+    MilestoneExpired { index: u32 },
+
+    #[error("Invalid threshold (threshold: {threshold}, total weight: {total_weight})")]
+    InvalidThreshold { threshold: u64, total_weight: u64 },
+
+    #[error("Invalid arbiters, they must be unique and have a non-zero weight")]
+    InvalidArbiters {},
+
+    #[error("The arbiter must be one of the arbiters")]
+    ArbiterNotInArbiters {},
+
+    #[error("Arbiter already approved this release")]
+    AlreadyVoted {},
+
+    #[error("Escrow is disputed")]
+    Disputed {},
+
+    #[error("Escrow is not disputed")]
+    NotDisputed {},
+
+    #[error("Recipient share must be between 0 and 1 (share: {share})")]
+    InvalidShare { share: Decimal },
+
+    #[error("Escrow vests its funds, use Claim or CancelVesting")]
+    VestingDefined {},
+
+    #[error("Escrow has no vesting schedule")]
+    NoVesting {},
+
+    #[error("Invalid vesting schedule, start <= cliff <= end is required and it cannot be combined with an expiration or milestones")]
+    InvalidVesting {},
+
+    #[error("Vesting already cancelled")]
+    VestingCancelled {},
+
+    #[error("Nothing to claim")]
+    NothingToClaim {},
+
+    #[error("Escrow has no release condition")]
+    NoReleaseCondition {},
+
+    #[error("Release condition not met")]
+    ConditionNotMet {},
+
+    #[error("A release condition cannot be combined with milestones or vesting")]
+    InvalidReleaseCondition {},
+
+    #[error("Invalid hashlock, it must be a hex encoded SHA-256 hash and the escrow needs an expiration")]
+    InvalidHashlock {},
+
+    #[error("Escrow has no hashlock")]
+    NoHashlock {},
+
+    #[error("Escrow is hash-time-locked, use Claim with the preimage")]
+    HashlockDefined {},
+
+    #[error("Preimage does not match the hashlock")]
+    InvalidPreimage {},
+
+    #[error("Fee rate must be lower than 1 (rate: {rate})")]
+    InvalidFeeRate { rate: Decimal },
+
+    #[error("No arbiter handover proposed")]
+    NoProposal {},
+
+    #[error("Arbiter handover proposal changed (proposed: {proposed})")]
+    StaleProposal { proposed: String },
+
+    #[error("No funds sent")]
+    NoFunds {},
+
+    #[error("Escrow id already in use (id: {id})")]
+    AlreadyInUse { id: String },
+
+    #[error("Cannot migrate from a different contract (contract: {contract})")]
+    CannotMigrate { contract: String },
+
+    #[error("Cannot migrate from a newer version (version: {version})")]
+    CannotMigrateVersion { version: String },
+}
+
+impl From<semver::Error> for ContractError {
+    fn from(err: semver::Error) -> Self {
+        Self::SemVer(err.to_string())
+    }
 }
@@ -1,3 +1,4 @@
+pub mod coin_helpers;
 pub mod contract;
 mod error;
 pub mod msg;
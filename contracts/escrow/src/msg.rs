@@ -1,5 +1,9 @@
+use crate::state::{
+    Arbiter, ArbiterProposal, Dispute, Fee, FeeTotals, Hashlock, PendingRelease, ReleaseCondition,
+};
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::{Addr, Coin};
+use cosmwasm_std::{Addr, Binary, Coin, Decimal, Timestamp};
+use cw20::{Cw20Coin, Cw20CoinVerified, Cw20ReceiveMsg};
 use cw_utils::Expiration;
 
 #[cw_serde]
@@ -13,15 +17,144 @@ pub struct InstantiateMsg {
     /// block time exceeds this value, the escrow is expired.
     /// Once an escrow is expired, it can be returned to the original funder (via "refund").
     pub expiration: Option<Expiration>,
+    /// Besides any native tokens, the escrow only accepts cw20 tokens
+    /// from the contracts listed here.
+    pub cw20_whitelist: Option<Vec<String>>,
+    /// Ordered deliverables the escrow is released against. When set, funds can only
+    /// be released with `ApproveMilestone`. Requires an expiration.
+    pub milestones: Option<Vec<MilestoneMsg>>,
+    /// Weighted arbiters that approve releases together, replacing `arbiter` for
+    /// approvals. A release is sent once the weights of its approvers reach `threshold`.
+    /// `arbiter` must be one of them.
+    pub arbiters: Option<Vec<ArbiterMsg>>,
+    /// Required approval weight, defaults to the total weight of all arbiters
+    pub threshold: Option<u64>,
+    /// Vests the funds to the recipient over time, who withdraws them with `Claim`.
+    /// Cannot be combined with an expiration or milestones.
+    pub vesting: Option<VestingMsg>,
+    /// Lets anyone release the escrow to the recipient with `ReleaseIfConditionMet`
+    /// once a query on another contract returns the expected result.
+    /// Cannot be combined with milestones or vesting.
+    pub release_condition: Option<ReleaseConditionMsg>,
+    /// Hex encoded SHA-256 hash locking the escrow (HTLC mode). Anyone presenting the
+    /// preimage with `Claim` before expiration releases the funds to the recipient,
+    /// afterwards they can only be refunded. Requires an expiration and cannot be
+    /// combined with milestones, vesting or a release condition.
+    pub hashlock: Option<String>,
+    /// Fee taken from every payout to a recipient, registry escrows included. Refunds are exempt.
+    pub fee: Option<FeeMsg>,
+}
+
+#[cw_serde]
+pub struct FeeMsg {
+    pub collector: String,
+    /// Fraction of every released token paid to the collector, rounded down
+    pub rate: Decimal,
+}
+
+#[cw_serde]
+pub struct ReleaseConditionMsg {
+    pub contract: String,
+    /// Smart query sent to the contract
+    pub query: Binary,
+    /// Expected JSON result, compared byte for byte with the query response
+    pub expected: Binary,
+}
+
+#[cw_serde]
+pub struct MigrateMsg {}
+
+#[cw_serde]
+pub struct VestingMsg {
+    /// Native tokens vested over the schedule
+    pub amount: Vec<Coin>,
+    /// Vesting starts at this time, defaults to the instantiation block time
+    pub start: Option<Timestamp>,
+    /// Nothing can be claimed before the cliff, after which the linear amount since
+    /// `start` becomes available at once
+    pub cliff: Option<Timestamp>,
+    /// Everything is vested at this time
+    pub end: Timestamp,
+}
+
+#[cw_serde]
+pub struct ArbiterMsg {
+    pub addr: String,
+    pub weight: u64,
+}
+
+#[cw_serde]
+pub struct MilestoneMsg {
+    pub title: String,
+    /// Native tokens released to the recipient once the milestone is approved
+    pub amount: Vec<Coin>,
+    /// The milestone can no longer be approved once the deadline is reached
+    pub deadline: Option<Expiration>,
 }
 
 #[cw_serde]
 pub enum ExecuteMsg {
     Approve {
-        // release some coins - if quantity is None, release all coins in balance
+        /// Registry escrow to release from. If None, the contract-level escrow is used.
+        id: Option<String>,
+        // release some coins - if quantity and cw20_quantity are both None, release all
+        // tokens in balance
         quantity: Option<Vec<Coin>>,
+        cw20_quantity: Option<Vec<Cw20Coin>>,
+    },
+    Refund {
+        /// Registry escrow to refund. If None, the contract-level escrow is used.
+        id: Option<String>,
     },
-    Refund {},
+    /// Receive deposits cw20 tokens from a whitelisted contract into the escrow
+    Receive(Cw20ReceiveMsg),
+    /// Create registers a new escrow under `id`, holding the native tokens sent along.
+    /// The sender becomes the source of the escrow.
+    Create {
+        id: String,
+        arbiter: String,
+        recipient: String,
+        expiration: Option<Expiration>,
+        cw20_whitelist: Option<Vec<String>>,
+    },
+    /// Deposit adds the native tokens sent along to the registry escrow `id`
+    Deposit { id: String },
+    /// TopUp adds the native tokens sent along to the contract-level escrow,
+    /// recording the sender as depositor. Refunds pay back what each depositor
+    /// still has in the escrow.
+    TopUp {},
+    /// Arbiter releases the amount of the milestone at `index` to the recipient
+    ApproveMilestone { index: u32 },
+    /// Recipient or source disputes the escrow. Approvals, refunds and top ups are
+    /// blocked until the arbiter resolves the dispute.
+    Dispute { reason: String },
+    /// Arbiter resolves the dispute by paying `recipient_share` of every held token to
+    /// the recipient and the rest to the source
+    Resolve { recipient_share: Decimal },
+    /// Recipient withdraws the vested tokens not claimed yet. In HTLC mode anyone
+    /// releases all held tokens to the recipient with the hex encoded `preimage`.
+    Claim { preimage: Option<String> },
+    /// Arbiter stops the vesting, refunding everything but the vested tokens still
+    /// to be claimed to the source
+    CancelVesting {},
+    /// Arbiter or source proposes to hand the arbiter role over to `arbiter`,
+    /// replacing any earlier proposal
+    UpdateArbiter { arbiter: String },
+    /// The party that did not propose the handover accepts it. `arbiter` must match
+    /// the current proposal.
+    AcceptArbiter { arbiter: String },
+    /// Recipient redirects the payouts of the escrow to `recipient`
+    UpdateRecipient { recipient: String },
+    /// Anyone releases all held tokens to the recipient once the release condition
+    /// is met
+    ReleaseIfConditionMet {},
+}
+
+#[cw_serde]
+pub enum ReceiveMsg {
+    /// Deposit adds the received cw20 tokens to the escrow balance. If id is None,
+    /// the contract-level escrow is used.
+    Deposit { id: Option<String> },
 }
 
 #[cw_serde]
@@ -30,9 +163,163 @@ pub enum QueryMsg {
     /// Returns a human-readable representation of the arbiter.
     #[returns(ArbiterResponse)]
     Arbiter {},
+    /// Returns the arbiter handover waiting to be accepted, if any.
+    #[returns(ArbiterProposalResponse)]
+    ArbiterProposal {},
+    /// Returns the parties, expiry status and balance of the registry escrow `id`,
+    /// or of the contract-level escrow if id is None.
+    #[returns(DetailsResponse)]
+    Details { id: Option<String> },
+    /// Returns the vesting schedule of the contract-level escrow and what is vested.
+    #[returns(VestingResponse)]
+    Vesting {},
+    /// Returns the release condition of the contract-level escrow and whether it is met.
+    #[returns(ReleaseConditionResponse)]
+    ReleaseCondition {},
+    /// Returns the fee and the fees collected so far.
+    #[returns(FeeResponse)]
+    Fee {},
+    /// Returns the hashlock of the contract-level escrow and the preimage once claimed.
+    #[returns(HashlockResponse)]
+    Hashlock {},
+    /// Returns the open dispute of the contract-level escrow, if any.
+    #[returns(DisputeResponse)]
+    Dispute {},
+    /// Lists the releases of the contract-level escrow that are still collecting
+    /// arbiter approvals.
+    #[returns(PendingReleasesResponse)]
+    PendingReleases {},
+    /// Lists the milestones of the contract-level escrow and their status.
+    #[returns(MilestonesResponse)]
+    Milestones {},
+    /// Lists the native tokens each depositor still has in the contract-level escrow.
+    /// Releases reduce every deposit in proportion and refunds clear them.
+    #[returns(DepositsResponse)]
+    Deposits {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Lists the ids of all registry escrows.
+    #[returns(ListResponse)]
+    List {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Lists the ids of the registry escrows with the given arbiter.
+    #[returns(ListResponse)]
+    ListByArbiter {
+        arbiter: String,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Lists the ids of the registry escrows with the given recipient.
+    #[returns(ListResponse)]
+    ListByRecipient {
+        recipient: String,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
 }
 
 #[cw_serde]
 pub struct ArbiterResponse {
     pub arbiter: Addr,
 }
+
+#[cw_serde]
+pub struct ArbiterProposalResponse {
+    pub proposal: Option<ArbiterProposal>,
+}
+
+#[cw_serde]
+pub struct DetailsResponse {
+    pub arbiter: Addr,
+    /// Weighted arbiters approving releases. Registry escrows only have their arbiter.
+    pub arbiters: Vec<Arbiter>,
+    /// Approval weight a release needs before it is sent
+    pub threshold: u64,
+    pub recipient: Addr,
+    pub source: Addr,
+    pub expiration: Option<Expiration>,
+    pub is_expired: bool,
+    pub cw20_whitelist: Vec<Addr>,
+    pub native_balance: Vec<Coin>,
+    pub cw20_balance: Vec<Cw20CoinVerified>,
+}
+
+#[cw_serde]
+pub struct ListResponse {
+    pub escrows: Vec<String>,
+}
+
+#[cw_serde]
+pub struct DepositInfo {
+    pub depositor: Addr,
+    pub amount: Vec<Coin>,
+}
+
+#[cw_serde]
+pub struct DepositsResponse {
+    pub deposits: Vec<DepositInfo>,
+}
+
+#[cw_serde]
+pub enum MilestoneStatus {
+    Pending,
+    Released,
+    Expired,
+}
+
+#[cw_serde]
+pub struct MilestoneResponse {
+    pub title: String,
+    pub amount: Vec<Coin>,
+    pub deadline: Option<Expiration>,
+    pub status: MilestoneStatus,
+    /// Block height the milestone was released at
+    pub released_at: Option<u64>,
+}
+
+#[cw_serde]
+pub struct MilestonesResponse {
+    pub milestones: Vec<MilestoneResponse>,
+}
+
+#[cw_serde]
+pub struct PendingReleasesResponse {
+    pub releases: Vec<PendingRelease>,
+}
+
+#[cw_serde]
+pub struct ReleaseConditionResponse {
+    pub condition: Option<ReleaseCondition>,
+    pub is_met: bool,
+}
+
+#[cw_serde]
+pub struct FeeResponse {
+    pub fee: Option<Fee>,
+    pub collected: FeeTotals,
+}
+
+#[cw_serde]
+pub struct HashlockResponse {
+    pub hashlock: Option<Hashlock>,
+}
+
+#[cw_serde]
+pub struct DisputeResponse {
+    pub dispute: Option<Dispute>,
+}
+
+#[cw_serde]
+pub struct VestingResponse {
+    pub amount: Vec<Coin>,
+    pub start: Timestamp,
+    pub cliff: Option<Timestamp>,
+    pub end: Timestamp,
+    pub cancelled_at: Option<Timestamp>,
+    pub vested: Vec<Coin>,
+    pub claimed: Vec<Coin>,
+    pub claimable: Vec<Coin>,
+}
@@ -1,6 +1,7 @@
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::Addr;
-use cw_storage_plus::Item;
+use cosmwasm_std::{Addr, Binary, Coin, Decimal, Timestamp, Uint128};
+use cw20::{Cw20Coin, Cw20CoinVerified};
+use cw_storage_plus::{Index, IndexList, IndexedMap, Item, Map, MultiIndex};
 use cw_utils::Expiration;
 
 #[cw_serde]
@@ -9,7 +10,260 @@ pub struct Config {
     pub recipient: Addr,
     pub source: Addr,
     pub expiration: Option<Expiration>,
+    /// cw20 token contracts that may be deposited into the escrow
+    pub cw20_whitelist: Vec<Addr>,
+    /// Arbiters approving releases, by default only `arbiter` with weight 1
+    pub arbiters: Vec<Arbiter>,
+    /// Approval weight needed to send a release
+    pub threshold: u64,
+}
+
+#[cw_serde]
+pub struct Arbiter {
+    pub addr: Addr,
+    pub weight: u64,
 }
 
 pub const CONFIG_KEY: &str = "config";
 pub const CONFIG: Item<Config> = Item::new(CONFIG_KEY);
+
+/// Config as stored by any version of the contract. Fields added after 0.11 are
+/// missing from older state and filled in by `migrate`.
+#[cw_serde]
+pub struct StoredConfig {
+    pub arbiter: Addr,
+    pub recipient: Addr,
+    pub source: Addr,
+    pub expiration: Option<Expiration>,
+    pub cw20_whitelist: Option<Vec<Addr>>,
+    pub arbiters: Option<Vec<Arbiter>>,
+    pub threshold: Option<u64>,
+}
+
+impl From<StoredConfig> for Config {
+    fn from(stored: StoredConfig) -> Self {
+        let arbiter = stored.arbiter;
+        let arbiters = stored.arbiters.unwrap_or_else(|| {
+            vec![Arbiter {
+                addr: arbiter.clone(),
+                weight: 1,
+            }]
+        });
+        let threshold = stored
+            .threshold
+            .unwrap_or_else(|| arbiters.iter().map(|arbiter| arbiter.weight).sum());
+        Config {
+            arbiter,
+            recipient: stored.recipient,
+            source: stored.source,
+            expiration: stored.expiration,
+            cw20_whitelist: stored.cw20_whitelist.unwrap_or_default(),
+            arbiters,
+            threshold,
+        }
+    }
+}
+
+pub const STORED_CONFIG: Item<StoredConfig> = Item::new(CONFIG_KEY);
+
+/// Escrowed amount per whitelisted cw20 token contract
+pub const CW20_BALANCES: Map<&Addr, Uint128> = Map::new("cw20_balances");
+
+#[cw_serde]
+pub struct Milestone {
+    pub title: String,
+    pub amount: Vec<Coin>,
+    pub deadline: Option<Expiration>,
+    /// Block height the milestone was released at, if approved
+    pub released_at: Option<u64>,
+}
+
+/// Milestones of the contract-level escrow, in the order given at instantiation
+pub const MILESTONES: Item<Vec<Milestone>> = Item::new("milestones");
+
+/// A release of the contract-level escrow the arbiters vote on
+#[cw_serde]
+pub enum Release {
+    /// Release the quantities to the recipient, everything if both are None
+    Approve {
+        recipient: Addr,
+        quantity: Option<Vec<Coin>>,
+        cw20_quantity: Option<Vec<Cw20Coin>>,
+    },
+    /// Release the milestone at index to the recipient
+    Milestone { recipient: Addr, index: u32 },
+    /// Settle the dispute, paying recipient_share of every token to the recipient
+    Resolve {
+        recipient: Addr,
+        recipient_share: Decimal,
+    },
+    /// Stop the vesting and refund the unvested tokens
+    CancelVesting {},
+}
+
+#[cw_serde]
+pub struct PendingRelease {
+    pub release: Release,
+    /// Arbiters that approved the release so far
+    pub signers: Vec<Addr>,
+    /// Sum of the signers' weights
+    pub weight: u64,
+}
+
+/// Releases collecting arbiter approvals, keyed by the serialized release
+pub const PENDING_RELEASES: Map<&[u8], PendingRelease> = Map::new("pending_releases");
+
+#[cw_serde]
+pub struct Dispute {
+    pub raised_by: Addr,
+    pub reason: String,
+    /// Block height the dispute was raised at
+    pub raised_at: u64,
+}
+
+/// Open dispute of the contract-level escrow
+pub const DISPUTE: Item<Dispute> = Item::new("dispute");
+
+#[cw_serde]
+pub struct Vesting {
+    pub amount: Vec<Coin>,
+    pub start: Timestamp,
+    pub cliff: Option<Timestamp>,
+    pub end: Timestamp,
+    /// Vested tokens already sent to the recipient
+    pub claimed: Vec<Coin>,
+    /// Vesting stops at this time once the arbiter cancels it
+    pub cancelled_at: Option<Timestamp>,
+}
+
+impl Vesting {
+    /// Returns the tokens vested at the given time
+    pub fn vested(&self, now: Timestamp) -> Vec<Coin> {
+        let now = match self.cancelled_at {
+            Some(cancelled_at) if cancelled_at < now => cancelled_at,
+            _ => now,
+        };
+        if now < self.cliff.unwrap_or(self.start) || now <= self.start {
+            return vec![];
+        }
+        if now >= self.end {
+            return self.amount.clone();
+        }
+
+        let elapsed = now.seconds() - self.start.seconds();
+        let duration = self.end.seconds() - self.start.seconds();
+        self.amount
+            .iter()
+            .map(|coin| Coin {
+                denom: coin.denom.clone(),
+                amount: coin.amount.multiply_ratio(elapsed, duration),
+            })
+            .filter(|coin| !coin.amount.is_zero())
+            .collect()
+    }
+}
+
+/// Vesting schedule of the contract-level escrow
+pub const VESTING: Item<Vesting> = Item::new("vesting");
+
+/// Smart query on another contract whose result releases the escrow
+#[cw_serde]
+pub struct ReleaseCondition {
+    pub contract: Addr,
+    pub query: Binary,
+    /// The condition is met when the query returns exactly these bytes
+    pub expected: Binary,
+}
+
+/// Release condition of the contract-level escrow
+pub const RELEASE_CONDITION: Item<ReleaseCondition> = Item::new("release_condition");
+
+/// Fee taken from every approved release of the contract-level escrow
+#[cw_serde]
+pub struct Fee {
+    pub collector: Addr,
+    /// Fraction of every released token paid to the collector, rounded down
+    pub rate: Decimal,
+}
+
+pub const FEE: Item<Fee> = Item::new("fee");
+
+/// Fees paid to the collector so far
+#[cw_serde]
+#[derive(Default)]
+pub struct FeeTotals {
+    pub native: Vec<Coin>,
+    pub cw20: Vec<Cw20CoinVerified>,
+}
+
+pub const FEES_COLLECTED: Item<FeeTotals> = Item::new("fees_collected");
+
+/// SHA-256 hashlock of an escrow in HTLC mode
+#[cw_serde]
+pub struct Hashlock {
+    /// Hex encoded SHA-256 hash of the secret
+    pub hash: String,
+    /// Hex encoded secret, revealed by the claim so the other side of a swap can use it
+    pub preimage: Option<String>,
+}
+
+/// Hashlock of the contract-level escrow
+pub const HASHLOCK: Item<Hashlock> = Item::new("hashlock");
+
+/// Arbiter handover proposed by the current arbiter or the source, waiting for the
+/// other party to accept it
+#[cw_serde]
+pub struct ArbiterProposal {
+    pub arbiter: Addr,
+    pub proposed_by: Addr,
+}
+
+pub const ARBITER_PROPOSAL: Item<ArbiterProposal> = Item::new("arbiter_proposal");
+
+/// Native tokens deposited into the contract-level escrow, per depositor
+pub const DEPOSITS: Map<&Addr, Vec<Coin>> = Map::new("deposits");
+
+/// An escrow created in the registry with `Create`, held next to the contract-level escrow
+#[cw_serde]
+pub struct Escrow {
+    pub arbiter: Addr,
+    pub recipient: Addr,
+    pub source: Addr,
+    pub expiration: Option<Expiration>,
+    /// cw20 token contracts that may be deposited into the escrow
+    pub cw20_whitelist: Vec<Addr>,
+    pub native_balance: Vec<Coin>,
+    pub cw20_balance: Vec<Cw20CoinVerified>,
+}
+
+pub struct EscrowIndexes<'a> {
+    pub arbiter: MultiIndex<'a, Addr, Escrow, String>,
+    pub recipient: MultiIndex<'a, Addr, Escrow, String>,
+}
+
+impl<'a> IndexList<Escrow> for EscrowIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Escrow>> + '_> {
+        let v: Vec<&dyn Index<Escrow>> = vec![&self.arbiter, &self.recipient];
+        Box::new(v.into_iter())
+    }
+}
+
+pub fn escrows<'a>() -> IndexedMap<'a, &'a str, Escrow, EscrowIndexes<'a>> {
+    let indexes = EscrowIndexes {
+        arbiter: MultiIndex::new(
+            |e: &Escrow| e.arbiter.clone(),
+            "escrows",
+            "escrows__arbiter",
+        ),
+        recipient: MultiIndex::new(
+            |e: &Escrow| e.recipient.clone(),
+            "escrows",
+            "escrows__recipient",
+        ),
+    };
+    IndexedMap::new("escrows", indexes)
+}
+
+/// Native tokens held by all registry escrows together. The contract's bank balance minus
+/// these is what belongs to the contract-level escrow.
+pub const REGISTRY_NATIVE_BALANCE: Item<Vec<Coin>> = Item::new("registry_native_balance");
@@ -1,84 +1,365 @@
-//! This integration test tries to run and call the generated wasm.
-//! It depends on a Wasm build being available, which you can create with `cargo wasm`.
-//! Then running `cargo integration-test` will validate we can properly call into that generated Wasm.
-//!
-//! You can easily convert unit tests to integration tests as follows:
-//! 1. Copy them over verbatim
-//! 2. Then change
-//!      let mut deps = mock_dependencies(20, &[]);
-//!    to
-//!      let mut deps = mock_instance(WASM, &[]);
-//! 3. If you access raw storage, where ever you see something like:
-//!      deps.storage.get(CONFIG_KEY).expect("no data stored");
-//!    replace it with:
-//!      deps.with_storage(|store| {
-//!          let data = store.get(CONFIG_KEY).expect("no data stored");
-//!          //...
-//!      });
-//! 4. Anywhere you see query(&deps, ...) you must replace it with query(&mut deps, ...)
-
-use cosmwasm_std::{
-    coins, Addr, BlockInfo, Coin, ContractInfo, Env, MessageInfo, Response, Timestamp,
-    TransactionInfo,
-};
-use cosmwasm_vm::testing::{instantiate, mock_info, mock_instance};
-use cosmwasm_vm::{from_slice, Storage};
+//! End-to-end tests running the escrow inside a simulated chain with cw-multi-test.
+//! Tokens are moved by a real bank module, so the tests assert the balances of the
+//! parties rather than the messages the contract emits.
 
-use cosmwasm_std::testing::MOCK_CONTRACT_ADDR;
-use cw_escrow::msg::InstantiateMsg;
-use cw_escrow::state::Config;
+use cosmwasm_std::{coin, coins, Addr, Coin, Empty};
+use cw_escrow::contract::{execute, instantiate, migrate, query};
+use cw_escrow::msg::{
+    DepositInfo, DepositsResponse, DetailsResponse, ExecuteMsg, InstantiateMsg, QueryMsg,
+};
+use cw_multi_test::{App, AppBuilder, Contract, ContractWrapper, Executor};
 use cw_utils::Expiration;
+use sha2::{Digest, Sha256};
+
+const CREATOR: &str = "creator";
+const FUNDER: &str = "funder";
+const ARBITER: &str = "verifies";
+const RECIPIENT: &str = "benefits";
+const DENOM: &str = "earth";
+
+fn escrow_contract() -> Box<dyn Contract<Empty>> {
+    let contract = ContractWrapper::new(execute, instantiate, query).with_migrate(migrate);
+    Box::new(contract)
+}
+
+fn mock_app() -> App {
+    AppBuilder::new().build(|router, _, storage| {
+        for (addr, amount) in [(CREATOR, 1000), (FUNDER, 500)] {
+            router
+                .bank
+                .init_balance(storage, &Addr::unchecked(addr), coins(amount, DENOM))
+                .unwrap();
+        }
+    })
+}
 
-// This line will test the output of cargo wasm
-static WASM: &[u8] = include_bytes!("../target/wasm32-unknown-unknown/release/cw_escrow.wasm");
-// You can uncomment this line instead to test productionified build from rust-optimizer
-// static WASM: &[u8] = include_bytes!("../contract.wasm");
+fn balance(app: &App, addr: &str) -> u128 {
+    app.wrap().query_balance(addr, DENOM).unwrap().amount.u128()
+}
 
-fn init_msg_expire_by_height(expiration: Expiration) -> InstantiateMsg {
+fn init_msg(expiration: Option<Expiration>) -> InstantiateMsg {
     InstantiateMsg {
-        arbiter: String::from("verifies"),
-        recipient: String::from("benefits"),
-        expiration: Some(expiration),
+        arbiter: String::from(ARBITER),
+        recipient: String::from(RECIPIENT),
+        expiration,
+        cw20_whitelist: None,
+        milestones: None,
+        arbiters: None,
+        threshold: None,
+        vesting: None,
+        release_condition: None,
+        hashlock: None,
+        fee: None,
+    }
+}
+
+fn approve(quantity: Option<Vec<Coin>>) -> ExecuteMsg {
+    ExecuteMsg::Approve {
+        id: None,
+        quantity,
+        cw20_quantity: None,
     }
 }
 
-fn mock_env_info_height(signer: &str, sent: &[Coin], height: u64, time: u64) -> (Env, MessageInfo) {
-    let env = Env {
-        block: BlockInfo {
-            height,
-            time: Timestamp::from_nanos(time),
-            chain_id: String::from("test"),
+fn details(app: &App, escrow: &Addr, id: Option<&str>) -> DetailsResponse {
+    app.wrap()
+        .query_wasm_smart(
+            escrow,
+            &QueryMsg::Details {
+                id: id.map(String::from),
+            },
+        )
+        .unwrap()
+}
+
+fn instantiate_escrow(app: &mut App, expiration: Option<Expiration>, funds: &[Coin]) -> Addr {
+    let code_id = app.store_code(escrow_contract());
+    app.instantiate_contract(
+        code_id,
+        Addr::unchecked(CREATOR),
+        &init_msg(expiration),
+        funds,
+        "escrow",
+        None,
+    )
+    .unwrap()
+}
+
+#[test]
+fn deposit_approve_expire_and_refund() {
+    let mut app = mock_app();
+    let expires = app.block_info().height + 100;
+    let escrow = instantiate_escrow(
+        &mut app,
+        Some(Expiration::AtHeight(expires)),
+        &coins(600, DENOM),
+    );
+    assert_eq!(balance(&app, CREATOR), 400);
+    assert_eq!(balance(&app, escrow.as_str()), 600);
+
+    // a second depositor tops the escrow up
+    app.execute_contract(
+        Addr::unchecked(FUNDER),
+        escrow.clone(),
+        &ExecuteMsg::TopUp {},
+        &coins(300, DENOM),
+    )
+    .unwrap();
+    assert_eq!(balance(&app, FUNDER), 200);
+    let res: DepositsResponse = app
+        .wrap()
+        .query_wasm_smart(
+            &escrow,
+            &QueryMsg::Deposits {
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        res.deposits,
+        vec![
+            DepositInfo {
+                depositor: Addr::unchecked(CREATOR),
+                amount: coins(600, DENOM),
+            },
+            DepositInfo {
+                depositor: Addr::unchecked(FUNDER),
+                amount: coins(300, DENOM),
+            },
+        ]
+    );
+
+    // only the arbiter can release, and only what the escrow holds
+    app.execute_contract(
+        Addr::unchecked(RECIPIENT),
+        escrow.clone(),
+        &approve(None),
+        &[],
+    )
+    .unwrap_err();
+    app.execute_contract(
+        Addr::unchecked(ARBITER),
+        escrow.clone(),
+        &approve(Some(coins(1000, DENOM))),
+        &[],
+    )
+    .unwrap_err();
+
+    // two partial releases
+    app.update_block(|block| block.height += 10);
+    app.execute_contract(
+        Addr::unchecked(ARBITER),
+        escrow.clone(),
+        &approve(Some(coins(250, DENOM))),
+        &[],
+    )
+    .unwrap();
+    assert_eq!(balance(&app, RECIPIENT), 250);
+    app.update_block(|block| block.height += 10);
+    app.execute_contract(
+        Addr::unchecked(ARBITER),
+        escrow.clone(),
+        &approve(Some(coins(150, DENOM))),
+        &[],
+    )
+    .unwrap();
+    assert_eq!(balance(&app, RECIPIENT), 400);
+    assert_eq!(balance(&app, escrow.as_str()), 500);
+    assert_eq!(
+        details(&app, &escrow, None).native_balance,
+        coins(500, DENOM)
+    );
+
+    // nothing can be refunded before expiration
+    app.execute_contract(
+        Addr::unchecked(CREATOR),
+        escrow.clone(),
+        &ExecuteMsg::Refund { id: None },
+        &[],
+    )
+    .unwrap_err();
+
+    // once expired, the arbiter can no longer release and the rest goes back to the
+    // depositors: the releases were paid out of both deposits, leaving 300 * 500 / 900
+    // for the funder
+    app.update_block(|block| block.height = expires);
+    assert!(details(&app, &escrow, None).is_expired);
+    app.execute_contract(
+        Addr::unchecked(ARBITER),
+        escrow.clone(),
+        &approve(None),
+        &[],
+    )
+    .unwrap_err();
+    app.execute_contract(
+        Addr::unchecked(FUNDER),
+        escrow.clone(),
+        &ExecuteMsg::Refund { id: None },
+        &[],
+    )
+    .unwrap();
+    assert_eq!(balance(&app, CREATOR), 734);
+    assert_eq!(balance(&app, FUNDER), 366);
+    assert_eq!(balance(&app, RECIPIENT), 400);
+    assert_eq!(balance(&app, escrow.as_str()), 0);
+
+    // and the deposits are settled
+    let res: DepositsResponse = app
+        .wrap()
+        .query_wasm_smart(
+            &escrow,
+            &QueryMsg::Deposits {
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+    assert!(res.deposits.is_empty());
+}
+
+#[test]
+fn registry_escrows_keep_their_own_funds() {
+    let mut app = mock_app();
+    let escrow = instantiate_escrow(&mut app, None, &coins(400, DENOM));
+
+    let expires = app.block_info().height + 50;
+    app.execute_contract(
+        Addr::unchecked(FUNDER),
+        escrow.clone(),
+        &ExecuteMsg::Create {
+            id: String::from("job"),
+            arbiter: String::from(ARBITER),
+            recipient: String::from("contractor"),
+            expiration: Some(Expiration::AtHeight(expires)),
+            cw20_whitelist: None,
         },
-        contract: ContractInfo {
-            address: Addr::unchecked(MOCK_CONTRACT_ADDR),
+        &coins(300, DENOM),
+    )
+    .unwrap();
+    assert_eq!(balance(&app, escrow.as_str()), 700);
+    assert_eq!(
+        details(&app, &escrow, None).native_balance,
+        coins(400, DENOM)
+    );
+    assert_eq!(
+        details(&app, &escrow, Some("job")).native_balance,
+        coins(300, DENOM)
+    );
+
+    // the contract-level escrow cannot spend the registry funds
+    app.execute_contract(
+        Addr::unchecked(ARBITER),
+        escrow.clone(),
+        &approve(Some(coins(500, DENOM))),
+        &[],
+    )
+    .unwrap_err();
+    app.execute_contract(
+        Addr::unchecked(ARBITER),
+        escrow.clone(),
+        &approve(None),
+        &[],
+    )
+    .unwrap();
+    assert_eq!(balance(&app, RECIPIENT), 400);
+
+    app.execute_contract(
+        Addr::unchecked(ARBITER),
+        escrow.clone(),
+        &ExecuteMsg::Approve {
+            id: Some(String::from("job")),
+            quantity: Some(vec![coin(100, DENOM)]),
+            cw20_quantity: None,
         },
-        transaction: Some(TransactionInfo { index: 3 }),
-    };
-    let info = mock_info(signer, sent);
-    (env, info)
+        &[],
+    )
+    .unwrap();
+    assert_eq!(balance(&app, "contractor"), 100);
+
+    app.update_block(|block| block.height = expires);
+    app.execute_contract(
+        Addr::unchecked(CREATOR),
+        escrow.clone(),
+        &ExecuteMsg::Refund {
+            id: Some(String::from("job")),
+        },
+        &[],
+    )
+    .unwrap();
+    assert_eq!(balance(&app, FUNDER), 400);
+    assert_eq!(balance(&app, escrow.as_str()), 0);
 }
 
 #[test]
-fn proper_initialization() {
-    let mut deps = mock_instance(WASM, &[]);
-    let msg = init_msg_expire_by_height(Expiration::AtHeight(1000));
-    let (env, info) = mock_env_info_height("creator", &coins(1000, "earth"), 876, 0);
-    let res: Response = instantiate(&mut deps, env, info, msg).unwrap();
-    assert_eq!(0, res.messages.len());
-
-    // it worked, let's query the state
-    deps.with_storage(|store| {
-        let state: Config = from_slice(&store.get(b"config").0.unwrap().unwrap(), 2048).unwrap();
-        assert_eq!(
-            state,
-            Config {
-                arbiter: Addr::unchecked("verifies"),
-                recipient: Addr::unchecked("benefits"),
-                source: Addr::unchecked("creator"),
-                expiration: Some(Expiration::AtHeight(1000))
-            }
-        );
-        Ok(())
-    })
+fn hashlocked_swap_claim_or_refund() {
+    let secret = b"swap secret";
+    let mut app = mock_app();
+    let code_id = app.store_code(escrow_contract());
+    let expires = app.block_info().height + 100;
+    let msg = InstantiateMsg {
+        hashlock: Some(hex::encode(Sha256::digest(secret))),
+        ..init_msg(Some(Expiration::AtHeight(expires)))
+    };
+    let claimed = app
+        .instantiate_contract(
+            code_id,
+            Addr::unchecked(CREATOR),
+            &msg,
+            &coins(300, DENOM),
+            "claimed",
+            None,
+        )
+        .unwrap();
+    let refunded = app
+        .instantiate_contract(
+            code_id,
+            Addr::unchecked(CREATOR),
+            &msg,
+            &coins(200, DENOM),
+            "refunded",
+            None,
+        )
+        .unwrap();
+
+    // the counterparty claims one swap with the secret before expiration
+    app.update_block(|block| block.height += 10);
+    app.execute_contract(
+        Addr::unchecked(RECIPIENT),
+        claimed,
+        &ExecuteMsg::Claim {
+            preimage: Some(hex::encode(secret)),
+        },
+        &[],
+    )
+    .unwrap();
+    assert_eq!(balance(&app, RECIPIENT), 300);
+
+    // the other one can only be refunded once expired
+    app.execute_contract(
+        Addr::unchecked(CREATOR),
+        refunded.clone(),
+        &ExecuteMsg::Refund { id: None },
+        &[],
+    )
+    .unwrap_err();
+    app.update_block(|block| block.height = expires);
+    app.execute_contract(
+        Addr::unchecked(RECIPIENT),
+        refunded.clone(),
+        &ExecuteMsg::Claim {
+            preimage: Some(hex::encode(secret)),
+        },
+        &[],
+    )
+    .unwrap_err();
+    app.execute_contract(
+        Addr::unchecked(CREATOR),
+        refunded,
+        &ExecuteMsg::Refund { id: None },
+        &[],
+    )
     .unwrap();
+    assert_eq!(balance(&app, CREATOR), 700);
+    assert_eq!(balance(&app, RECIPIENT), 300);
 }
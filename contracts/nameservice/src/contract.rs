@@ -1,14 +1,26 @@
 use cosmwasm_std::{
-    entry_point, to_binary, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdError, StdResult,
+    entry_point, to_binary, Binary, Coin, Deps, DepsMut, Env, MessageInfo, Order, Response,
+    StdError, StdResult, Storage, Timestamp, Uint128, Uint64,
 };
 
 use crate::coin_helpers::assert_sent_sufficient_coin;
 use crate::error::ContractError;
-use crate::msg::{ConfigResponse, ExecuteMsg, InstantiateMsg, QueryMsg, ResolveRecordResponse};
-use crate::state::{Config, NameRecord, CONFIG, NAME_RESOLVER};
+use crate::msg::{
+    ConfigResponse, ExecuteMsg, InstantiateMsg, QueryMsg, Record, RecordsResponse,
+    ResolveRecordResponse,
+};
+use crate::state::{Config, NameRecord, CONFIG, NAME_RESOLVER, RECORDS};
 
 const MIN_NAME_LENGTH: u64 = 3;
 const MAX_NAME_LENGTH: u64 = 64;
+const DEFAULT_REGISTRATION_PERIOD: u64 = 365 * 24 * 60 * 60;
+const DEFAULT_GRACE_PERIOD: u64 = 30 * 24 * 60 * 60;
+const MAX_GRACE_PERIOD: u64 = 365 * 24 * 60 * 60;
+const MAX_RECORD_KEY_LENGTH: u64 = 64;
+const MAX_RECORD_VALUE_LENGTH: u64 = 1024;
+const MAX_RECORDS: u64 = 32;
+// record key holding the address a name resolves to instead of its owner
+const ADDRESS_KEY: &str = "address";
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
@@ -16,10 +28,22 @@ pub fn instantiate(
     _env: Env,
     _info: MessageInfo,
     msg: InstantiateMsg,
-) -> Result<Response, StdError> {
+) -> Result<Response, ContractError> {
+    if msg.registration_period == Some(0) {
+        return Err(ContractError::InvalidRegistrationPeriod {});
+    }
+    if msg.grace_period.unwrap_or_default() > MAX_GRACE_PERIOD {
+        return Err(ContractError::GracePeriodTooLong {
+            max_grace_period: MAX_GRACE_PERIOD,
+        });
+    }
     let config = Config {
         purchase_price: msg.purchase_price,
         transfer_price: msg.transfer_price,
+        registration_period: msg
+            .registration_period
+            .unwrap_or(DEFAULT_REGISTRATION_PERIOD),
+        grace_period: msg.grace_period.unwrap_or(DEFAULT_GRACE_PERIOD),
     };
     CONFIG.save(deps.storage, &config)?;
 
@@ -34,39 +58,116 @@ pub fn execute(
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
     match msg {
-        ExecuteMsg::Register { name } => execute_register(deps, env, info, name),
+        ExecuteMsg::Register { name, periods } => execute_register(deps, env, info, name, periods),
         ExecuteMsg::Transfer { name, to } => execute_transfer(deps, env, info, name, to),
+        ExecuteMsg::Renew { name, periods } => execute_renew(deps, env, info, name, periods),
+        ExecuteMsg::SetRecord { name, key, value } => {
+            execute_set_record(deps, env, info, name, key, value)
+        }
     }
 }
 
 pub fn execute_register(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     name: String,
+    periods: Option<u32>,
 ) -> Result<Response, ContractError> {
     // we only need to check here - at point of registration
     validate_name(&name)?;
     let config = CONFIG.load(deps.storage)?;
-    assert_sent_sufficient_coin(&info.funds, config.purchase_price)?;
+    let periods = periods.unwrap_or(1);
+    assert_sent_sufficient_coin(&info.funds, registration_price(&config, periods)?)?;
 
     let key = name.as_bytes();
-    let record = NameRecord { owner: info.sender };
-
-    if (NAME_RESOLVER.may_load(deps.storage, key)?).is_some() {
-        // name is already taken
-        return Err(ContractError::NameTaken { name });
+    if let Some(record) = NAME_RESOLVER.may_load(deps.storage, key)? {
+        // name is taken until the grace period after its expiry has passed
+        let available = add_seconds(record.expires, config.grace_period)?;
+        if env.block.time < available {
+            return Err(ContractError::NameTaken { name });
+        }
     }
 
-    // name is available
+    // name is available, records of a previous registration are dropped
+    clear_records(deps.storage, key)?;
+    let record = NameRecord {
+        owner: info.sender,
+        expires: extend(env.block.time, &config, periods)?,
+    };
     NAME_RESOLVER.save(deps.storage, key, &record)?;
 
     Ok(Response::default())
 }
 
+pub fn execute_renew(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    name: String,
+    periods: Option<u32>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let periods = periods.unwrap_or(1);
+    assert_sent_sufficient_coin(&info.funds, registration_price(&config, periods)?)?;
+
+    let key = name.as_bytes();
+    NAME_RESOLVER.update(deps.storage, key, |record| {
+        if let Some(mut record) = record {
+            // once the grace period is over the name is up for registration
+            if env.block.time >= add_seconds(record.expires, config.grace_period)? {
+                return Err(ContractError::NameExpired { name: name.clone() });
+            }
+            // during the grace period the name is still reserved for its owner
+            if env.block.time >= record.expires && info.sender != record.owner {
+                return Err(ContractError::Unauthorized {});
+            }
+
+            record.expires = extend(record.expires, &config, periods)?;
+            Ok(record)
+        } else {
+            Err(ContractError::NameNotExists { name: name.clone() })
+        }
+    })?;
+    Ok(Response::default())
+}
+
+/// registration_price returns what registering for the given number of periods costs
+fn registration_price(config: &Config, periods: u32) -> Result<Option<Coin>, ContractError> {
+    if periods == 0 {
+        return Err(ContractError::InvalidPeriods {});
+    }
+    match &config.purchase_price {
+        Some(price) => {
+            let amount = price
+                .amount
+                .checked_mul(Uint128::from(periods))
+                .map_err(StdError::from)?;
+            Ok(Some(Coin::new(amount.u128(), &price.denom)))
+        }
+        None => Ok(None),
+    }
+}
+
+fn extend(from: Timestamp, config: &Config, periods: u32) -> StdResult<Timestamp> {
+    let seconds = config
+        .registration_period
+        .checked_mul(periods.into())
+        .ok_or_else(|| StdError::generic_err("Registration too long"))?;
+    add_seconds(from, seconds)
+}
+
+/// add_seconds is Timestamp::plus_seconds, erroring instead of panicking on overflow
+fn add_seconds(from: Timestamp, seconds: u64) -> StdResult<Timestamp> {
+    let nanos = Uint64::new(seconds)
+        .checked_mul(Uint64::new(1_000_000_000))?
+        .checked_add(Uint64::new(from.nanos()))?;
+    Ok(Timestamp::from_nanos(nanos.u64()))
+}
+
 pub fn execute_transfer(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     name: String,
     to: String,
@@ -81,6 +182,9 @@ pub fn execute_transfer(
             if info.sender != record.owner {
                 return Err(ContractError::Unauthorized {});
             }
+            if env.block.time >= record.expires {
+                return Err(ContractError::NameExpired { name: name.clone() });
+            }
 
             record.owner = new_owner.clone();
             Ok(record)
@@ -88,29 +192,113 @@ pub fn execute_transfer(
             Err(ContractError::NameNotExists { name: name.clone() })
         }
     })?;
+    // the records were set by the previous owner
+    clear_records(deps.storage, key)?;
+    Ok(Response::default())
+}
+
+pub fn execute_set_record(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    name: String,
+    key: String,
+    value: String,
+) -> Result<Response, ContractError> {
+    let name_key = name.as_bytes();
+    let record = NAME_RESOLVER
+        .may_load(deps.storage, name_key)?
+        .ok_or_else(|| ContractError::NameNotExists { name: name.clone() })?;
+    if info.sender != record.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    if env.block.time >= record.expires {
+        return Err(ContractError::NameExpired { name });
+    }
+
+    validate_record(&key, &value)?;
+    if key == ADDRESS_KEY && !value.is_empty() {
+        deps.api.addr_validate(&value)?;
+    }
+
+    if value.is_empty() {
+        RECORDS.remove(deps.storage, (name_key, &key));
+    } else {
+        let is_new = !RECORDS.has(deps.storage, (name_key, &key));
+        let count = RECORDS
+            .prefix(name_key)
+            .keys_raw(deps.storage, None, None, Order::Ascending)
+            .count() as u64;
+        if is_new && count >= MAX_RECORDS {
+            return Err(ContractError::TooManyRecords {
+                max_records: MAX_RECORDS,
+            });
+        }
+        RECORDS.save(deps.storage, (name_key, &key), &value)?;
+    }
     Ok(Response::default())
 }
 
+fn clear_records(storage: &mut dyn Storage, name_key: &[u8]) -> StdResult<()> {
+    let keys = RECORDS
+        .prefix(name_key)
+        .keys(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<String>>>()?;
+    for key in keys {
+        RECORDS.remove(storage, (name_key, &key));
+    }
+    Ok(())
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::ResolveRecord { name } => query_resolver(deps, env, name),
         QueryMsg::Config {} => to_binary::<ConfigResponse>(&CONFIG.load(deps.storage)?.into()),
+        QueryMsg::Records { name } => query_records(deps, env, name),
     }
 }
 
-fn query_resolver(deps: Deps, _env: Env, name: String) -> StdResult<Binary> {
+fn query_resolver(deps: Deps, env: Env, name: String) -> StdResult<Binary> {
     let key = name.as_bytes();
 
-    let address = match NAME_RESOLVER.may_load(deps.storage, key)? {
-        Some(record) => Some(String::from(&record.owner)),
-        None => None,
+    // expired names resolve to nothing
+    let resp = match NAME_RESOLVER.may_load(deps.storage, key)? {
+        Some(record) if env.block.time < record.expires => {
+            // the address record takes precedence over the owner
+            let address = RECORDS
+                .may_load(deps.storage, (key, ADDRESS_KEY))?
+                .unwrap_or_else(|| String::from(&record.owner));
+            ResolveRecordResponse {
+                address: Some(address),
+                expires: Some(record.expires),
+            }
+        }
+        _ => ResolveRecordResponse {
+            address: None,
+            expires: None,
+        },
     };
-    let resp = ResolveRecordResponse { address };
 
     to_binary(&resp)
 }
 
+fn query_records(deps: Deps, env: Env, name: String) -> StdResult<Binary> {
+    let key = name.as_bytes();
+
+    // expired names have no records
+    let records = match NAME_RESOLVER.may_load(deps.storage, key)? {
+        Some(record) if env.block.time < record.expires => RECORDS
+            .prefix(key)
+            .range(deps.storage, None, None, Order::Ascending)
+            .map(|item| item.map(|(key, value)| Record { key, value }))
+            .collect::<StdResult<Vec<_>>>()?,
+        _ => vec![],
+    };
+
+    to_binary(&RecordsResponse { records })
+}
+
 // let's not import a regexp library and just do these checks by hand
 fn invalid_char(c: char) -> bool {
     let is_valid =
@@ -142,3 +330,29 @@ fn validate_name(name: &str) -> Result<(), ContractError> {
         }
     }
 }
+
+/// validate_record returns an error if the record key or value is invalid
+/// (keys are 1-64 characters like names, values at most 1024 bytes)
+fn validate_record(key: &str, value: &str) -> Result<(), ContractError> {
+    let length = key.len() as u64;
+    if key.is_empty() {
+        return Err(ContractError::EmptyRecordKey {});
+    }
+    if length > MAX_RECORD_KEY_LENGTH {
+        return Err(ContractError::RecordKeyTooLong {
+            length,
+            max_length: MAX_RECORD_KEY_LENGTH,
+        });
+    }
+    if let Some(c) = key.chars().find(|c| invalid_char(*c)) {
+        return Err(ContractError::InvalidCharacter { c });
+    }
+    let length = value.len() as u64;
+    if length > MAX_RECORD_VALUE_LENGTH {
+        return Err(ContractError::RecordValueTooLong {
+            length,
+            max_length: MAX_RECORD_VALUE_LENGTH,
+        });
+    }
+    Ok(())
+}
@@ -15,6 +15,18 @@ pub enum ContractError {
     #[error("Name does not exist (name {name})")]
     NameNotExists { name: String },
 
+    #[error("Name has expired (name {name})")]
+    NameExpired { name: String },
+
+    #[error("Registration periods must be greater than zero")]
+    InvalidPeriods {},
+
+    #[error("Registration period must be greater than zero seconds")]
+    InvalidRegistrationPeriod {},
+
+    #[error("Grace period too long (max_grace_period {max_grace_period})")]
+    GracePeriodTooLong { max_grace_period: u64 },
+
     #[error("Name has been taken (name {name})")]
     NameTaken { name: String },
 
@@ -26,4 +38,16 @@ pub enum ContractError {
 
     #[error("Invalid character(char {c}")]
     InvalidCharacter { c: char },
+
+    #[error("Record key must not be empty")]
+    EmptyRecordKey {},
+
+    #[error("Record key too long (length {length} max_length {max_length})")]
+    RecordKeyTooLong { length: u64, max_length: u64 },
+
+    #[error("Record value too long (length {length} max_length {max_length})")]
+    RecordValueTooLong { length: u64, max_length: u64 },
+
+    #[error("Too many records (max_records {max_records})")]
+    TooManyRecords { max_records: u64 },
 }
@@ -1,39 +1,87 @@
 use crate::state::Config;
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::Coin;
+use cosmwasm_std::{Coin, Timestamp};
 
 #[cw_serde]
 pub struct InstantiateMsg {
+    /// Price of one registration period
     pub purchase_price: Option<Coin>,
     pub transfer_price: Option<Coin>,
+    /// Length of a registration period in seconds, defaults to a year
+    pub registration_period: Option<u64>,
+    /// Seconds after expiry during which only the owner can renew, defaults to 30 days.
+    /// At most a year.
+    pub grace_period: Option<u64>,
 }
 
 #[cw_serde]
 pub enum ExecuteMsg {
-    Register { name: String },
-    Transfer { name: String, to: String },
+    /// Register an available name for a number of registration periods (defaults to 1),
+    /// paying purchase_price for each of them
+    Register {
+        name: String,
+        periods: Option<u32>,
+    },
+    Transfer {
+        name: String,
+        to: String,
+    },
+    /// Extend the registration of a name by a number of periods (defaults to 1), paying
+    /// purchase_price for each of them. Expired names can be renewed by their owner
+    /// during the grace period.
+    Renew {
+        name: String,
+        periods: Option<u32>,
+    },
+    /// Set a resolver record of a name, or remove it if value is empty. Only the owner
+    /// of an unexpired name can set records. Well known keys are `address` (the
+    /// address the name resolves to instead of the owner), `address.<chain>`, `url`,
+    /// `avatar`, `description` and `contenthash`.
+    SetRecord {
+        name: String,
+        key: String,
+        value: String,
+    },
 }
 
 #[cw_serde]
 #[derive(QueryResponses)]
 pub enum QueryMsg {
-    // ResolveAddress returns the current address that the name resolves to
+    // ResolveAddress returns the current address that the name resolves to, if the
+    // name is registered and not expired
     #[returns(ResolveRecordResponse)]
     ResolveRecord { name: String },
     #[returns(ConfigResponse)]
     Config {},
+    // Records returns all resolver records of an unexpired name
+    #[returns(RecordsResponse)]
+    Records { name: String },
 }
 
 // We define a custom struct for each query response
 #[cw_serde]
 pub struct ResolveRecordResponse {
     pub address: Option<String>,
+    pub expires: Option<Timestamp>,
+}
+
+#[cw_serde]
+pub struct Record {
+    pub key: String,
+    pub value: String,
+}
+
+#[cw_serde]
+pub struct RecordsResponse {
+    pub records: Vec<Record>,
 }
 
 #[cw_serde]
 pub struct ConfigResponse {
     pub purchase_price: Option<Coin>,
     pub transfer_price: Option<Coin>,
+    pub registration_period: u64,
+    pub grace_period: u64,
 }
 
 impl From<Config> for ConfigResponse {
@@ -41,6 +89,8 @@ impl From<Config> for ConfigResponse {
         ConfigResponse {
             purchase_price: config.purchase_price,
             transfer_price: config.transfer_price,
+            registration_period: config.registration_period,
+            grace_period: config.grace_period,
         }
     }
 }
@@ -1,17 +1,27 @@
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::{Addr, Coin};
+use cosmwasm_std::{Addr, Coin, Timestamp};
 use cw_storage_plus::{Item, Map};
 
 #[cw_serde]
 pub struct Config {
+    /// Price of one registration period
     pub purchase_price: Option<Coin>,
     pub transfer_price: Option<Coin>,
+    /// Length of a registration period in seconds
+    pub registration_period: u64,
+    /// Seconds after expiry during which only the owner can renew the name
+    pub grace_period: u64,
 }
 
 #[cw_serde]
 pub struct NameRecord {
     pub owner: Addr,
+    /// The name stops resolving at this time and can be registered by anyone once the
+    /// grace period has passed
+    pub expires: Timestamp,
 }
 
 pub const CONFIG: Item<Config> = Item::new("config");
 pub const NAME_RESOLVER: Map<&[u8], NameRecord> = Map::new("name_resolver");
+/// Resolver records of a name by key, see `ExecuteMsg::SetRecord`
+pub const RECORDS: Map<(&[u8], &str), String> = Map::new("records");
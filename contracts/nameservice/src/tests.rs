@@ -1,11 +1,13 @@
 #[cfg(test)]
 mod test_module {
     use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
-    use cosmwasm_std::{coin, coins, from_binary, Coin, Deps, DepsMut};
+    use cosmwasm_std::{coin, coins, from_binary, Coin, Deps, DepsMut, Env, StdError};
 
     use crate::contract::{execute, instantiate, query};
     use crate::error::ContractError;
-    use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg, ResolveRecordResponse};
+    use crate::msg::{
+        ExecuteMsg, InstantiateMsg, QueryMsg, Record, RecordsResponse, ResolveRecordResponse,
+    };
     use crate::state::Config;
 
     fn assert_name_owner(deps: Deps, name: &str, owner: &str) {
@@ -32,6 +34,8 @@ mod test_module {
         let msg = InstantiateMsg {
             purchase_price: Some(purchase_price),
             transfer_price: Some(transfer_price),
+            registration_period: None,
+            grace_period: None,
         };
 
         let info = mock_info("creator", &coins(2, "token"));
@@ -43,6 +47,8 @@ mod test_module {
         let msg = InstantiateMsg {
             purchase_price: None,
             transfer_price: None,
+            registration_period: None,
+            grace_period: None,
         };
 
         let info = mock_info("creator", &coins(2, "token"));
@@ -55,6 +61,7 @@ mod test_module {
         let info = mock_info("alice_key", sent);
         let msg = ExecuteMsg::Register {
             name: "alice".to_string(),
+            periods: None,
         };
         let _res = execute(deps, mock_env(), info, msg)
             .expect("contract successfully handles Register message");
@@ -71,6 +78,8 @@ mod test_module {
             Config {
                 purchase_price: None,
                 transfer_price: None,
+                registration_period: 31_536_000,
+                grace_period: 2_592_000,
             },
         );
     }
@@ -86,10 +95,66 @@ mod test_module {
             Config {
                 purchase_price: Some(coin(3, "token")),
                 transfer_price: Some(coin(4, "token")),
+                registration_period: 31_536_000,
+                grace_period: 2_592_000,
             },
         );
     }
 
+    #[test]
+    fn cannot_init_with_zero_registration_period() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            purchase_price: None,
+            transfer_price: None,
+            registration_period: Some(0),
+            grace_period: None,
+        };
+        let info = mock_info("creator", &[]);
+        match instantiate(deps.as_mut(), mock_env(), info, msg) {
+            Ok(_) => panic!("Must return error"),
+            Err(ContractError::InvalidRegistrationPeriod {}) => {}
+            Err(e) => panic!("Unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn cannot_init_with_too_long_grace_period() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            purchase_price: None,
+            transfer_price: None,
+            registration_period: None,
+            grace_period: Some(u64::MAX),
+        };
+        let info = mock_info("creator", &[]);
+        match instantiate(deps.as_mut(), mock_env(), info, msg) {
+            Ok(_) => panic!("Must return error"),
+            Err(ContractError::GracePeriodTooLong { .. }) => {}
+            Err(e) => panic!("Unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn register_fails_when_expiry_overflows() {
+        let mut deps = mock_dependencies();
+        mock_init_no_price(deps.as_mut());
+
+        // a thousand years do not fit into a timestamp
+        let info = mock_info("alice_key", &[]);
+        let msg = ExecuteMsg::Register {
+            name: "alice".to_string(),
+            periods: Some(1000),
+        };
+        match execute(deps.as_mut(), mock_env(), info, msg) {
+            Ok(_) => panic!("Must return error"),
+            Err(ContractError::Std(StdError::Overflow { .. })) => {}
+            Err(e) => panic!("Unexpected error: {:?}", e),
+        }
+    }
+
     #[test]
     fn register_available_name_and_query_works() {
         let mut deps = mock_dependencies();
@@ -110,6 +175,7 @@ mod test_module {
         let info = mock_info("bob_key", &coins(5, "token"));
         let msg = ExecuteMsg::Register {
             name: "bob".to_string(),
+            periods: None,
         };
 
         let _res = execute(deps.as_mut(), mock_env(), info, msg)
@@ -130,6 +196,7 @@ mod test_module {
         let info = mock_info("bob_key", &coins(2, "token"));
         let msg = ExecuteMsg::Register {
             name: "alice".to_string(),
+            periods: None,
         };
         let res = execute(deps.as_mut(), mock_env(), info, msg);
 
@@ -142,6 +209,7 @@ mod test_module {
         let info = mock_info("alice_key", &coins(2, "token"));
         let msg = ExecuteMsg::Register {
             name: "alice".to_string(),
+            periods: None,
         };
         let res = execute(deps.as_mut(), mock_env(), info, msg);
 
@@ -161,6 +229,7 @@ mod test_module {
         // hi is too short
         let msg = ExecuteMsg::Register {
             name: "hi".to_string(),
+            periods: None,
         };
         match execute(deps.as_mut(), mock_env(), info.clone(), msg) {
             Ok(_) => panic!("Must return error"),
@@ -171,6 +240,7 @@ mod test_module {
         // 65 chars is too long
         let msg = ExecuteMsg::Register {
             name: "01234567890123456789012345678901234567890123456789012345678901234".to_string(),
+            periods: None,
         };
         match execute(deps.as_mut(), mock_env(), info.clone(), msg) {
             Ok(_) => panic!("Must return error"),
@@ -181,6 +251,7 @@ mod test_module {
         // no upper case...
         let msg = ExecuteMsg::Register {
             name: "LOUD".to_string(),
+            periods: None,
         };
         match execute(deps.as_mut(), mock_env(), info.clone(), msg) {
             Ok(_) => panic!("Must return error"),
@@ -190,6 +261,7 @@ mod test_module {
         // ... or spaces
         let msg = ExecuteMsg::Register {
             name: "two words".to_string(),
+            periods: None,
         };
         match execute(deps.as_mut(), mock_env(), info, msg) {
             Ok(_) => panic!("Must return error"),
@@ -207,6 +279,7 @@ mod test_module {
         let info = mock_info("alice_key", &[]);
         let msg = ExecuteMsg::Register {
             name: "alice".to_string(),
+            periods: None,
         };
 
         let res = execute(deps.as_mut(), mock_env(), info, msg);
@@ -227,6 +300,7 @@ mod test_module {
         let info = mock_info("alice_key", &coins(2, "earth"));
         let msg = ExecuteMsg::Register {
             name: "alice".to_string(),
+            periods: None,
         };
 
         let res = execute(deps.as_mut(), mock_env(), info, msg);
@@ -369,4 +443,253 @@ mod test_module {
         let value: ResolveRecordResponse = from_binary(&res).unwrap();
         assert_eq!(None, value.address);
     }
+
+    fn query_record(deps: Deps, env: Env, name: &str) -> ResolveRecordResponse {
+        let res = query(
+            deps,
+            env,
+            QueryMsg::ResolveRecord {
+                name: name.to_string(),
+            },
+        )
+        .unwrap();
+        from_binary(&res).unwrap()
+    }
+
+    fn env_after(seconds: u64) -> Env {
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(seconds);
+        env
+    }
+
+    #[test]
+    fn register_charges_purchase_price_per_period() {
+        let mut deps = mock_dependencies();
+        mock_init_with_price(deps.as_mut(), coin(2, "token"), coin(2, "token"));
+
+        let msg = ExecuteMsg::Register {
+            name: "alice".to_string(),
+            periods: Some(3),
+        };
+        let info = mock_info("alice_key", &coins(5, "token"));
+        match execute(deps.as_mut(), mock_env(), info, msg.clone()) {
+            Ok(_) => panic!("register call should fail with insufficient fees"),
+            Err(ContractError::InsufficientFundsSend {}) => {}
+            Err(e) => panic!("Unexpected error: {:?}", e),
+        }
+
+        let info = mock_info("alice_key", &coins(6, "token"));
+        let _res = execute(deps.as_mut(), mock_env(), info, msg)
+            .expect("contract successfully handles Register message");
+
+        let value = query_record(deps.as_ref(), mock_env(), "alice");
+        assert_eq!(
+            value.expires,
+            Some(mock_env().block.time.plus_seconds(3 * 31_536_000))
+        );
+    }
+
+    #[test]
+    fn expired_name_can_be_renewed_during_grace_period() {
+        let mut deps = mock_dependencies();
+        mock_init_no_price(deps.as_mut());
+        mock_alice_registers_name(deps.as_mut(), &[]);
+
+        // expired names resolve to nothing
+        let value = query_record(deps.as_ref(), env_after(31_536_000), "alice");
+        assert_eq!(None, value.address);
+
+        // but cannot be registered by others during the grace period
+        let info = mock_info("bob_key", &[]);
+        let msg = ExecuteMsg::Register {
+            name: "alice".to_string(),
+            periods: None,
+        };
+        match execute(deps.as_mut(), env_after(31_536_000), info, msg) {
+            Ok(_) => panic!("Must return error"),
+            Err(ContractError::NameTaken { .. }) => {}
+            Err(e) => panic!("Unexpected error: {:?}", e),
+        }
+
+        // nor renewed by them
+        let info = mock_info("bob_key", &[]);
+        let msg = ExecuteMsg::Renew {
+            name: "alice".to_string(),
+            periods: None,
+        };
+        match execute(deps.as_mut(), env_after(31_536_000), info, msg) {
+            Ok(_) => panic!("Must return error"),
+            Err(ContractError::Unauthorized {}) => {}
+            Err(e) => panic!("Unexpected error: {:?}", e),
+        }
+
+        // renewing extends the registration from its previous expiry
+        let info = mock_info("alice_key", &[]);
+        let msg = ExecuteMsg::Renew {
+            name: "alice".to_string(),
+            periods: None,
+        };
+        let _res = execute(deps.as_mut(), env_after(31_536_000), info, msg)
+            .expect("contract successfully handles Renew message");
+        let value = query_record(deps.as_ref(), env_after(31_536_000), "alice");
+        assert_eq!(Some("alice_key".to_string()), value.address);
+        assert_eq!(
+            value.expires,
+            Some(mock_env().block.time.plus_seconds(2 * 31_536_000))
+        );
+    }
+
+    #[test]
+    fn expired_name_is_available_after_grace_period() {
+        let mut deps = mock_dependencies();
+        mock_init_no_price(deps.as_mut());
+        mock_alice_registers_name(deps.as_mut(), &[]);
+        let env = env_after(31_536_000 + 2_592_000);
+
+        // too late to renew
+        let info = mock_info("alice_key", &[]);
+        let msg = ExecuteMsg::Renew {
+            name: "alice".to_string(),
+            periods: None,
+        };
+        match execute(deps.as_mut(), env.clone(), info, msg) {
+            Ok(_) => panic!("Must return error"),
+            Err(ContractError::NameExpired { name }) => assert_eq!(name, "alice"),
+            Err(e) => panic!("Unexpected error: {:?}", e),
+        }
+
+        // anyone can register it again
+        let info = mock_info("bob_key", &[]);
+        let msg = ExecuteMsg::Register {
+            name: "alice".to_string(),
+            periods: None,
+        };
+        let _res = execute(deps.as_mut(), env.clone(), info, msg)
+            .expect("contract successfully handles Register message");
+        let value = query_record(deps.as_ref(), env, "alice");
+        assert_eq!(Some("bob_key".to_string()), value.address);
+    }
+
+    fn set_record(
+        deps: DepsMut,
+        sender: &str,
+        key: &str,
+        value: &str,
+    ) -> Result<(), ContractError> {
+        let info = mock_info(sender, &[]);
+        let msg = ExecuteMsg::SetRecord {
+            name: "alice".to_string(),
+            key: key.to_string(),
+            value: value.to_string(),
+        };
+        execute(deps, mock_env(), info, msg).map(|_| ())
+    }
+
+    fn query_records(deps: Deps, env: Env) -> Vec<Record> {
+        let res = query(
+            deps,
+            env,
+            QueryMsg::Records {
+                name: "alice".to_string(),
+            },
+        )
+        .unwrap();
+        let value: RecordsResponse = from_binary(&res).unwrap();
+        value.records
+    }
+
+    #[test]
+    fn set_records_and_query_works() {
+        let mut deps = mock_dependencies();
+        mock_init_no_price(deps.as_mut());
+        mock_alice_registers_name(deps.as_mut(), &[]);
+
+        // only the owner sets records
+        match set_record(deps.as_mut(), "bob_key", "url", "https://alice.example") {
+            Ok(_) => panic!("Must return error"),
+            Err(ContractError::Unauthorized {}) => {}
+            Err(e) => panic!("Unexpected error: {:?}", e),
+        }
+
+        set_record(deps.as_mut(), "alice_key", "url", "https://alice.example").unwrap();
+        set_record(deps.as_mut(), "alice_key", "address", "alice_wallet").unwrap();
+        set_record(deps.as_mut(), "alice_key", "contenthash", "ipfs://bafy").unwrap();
+        assert_eq!(
+            query_records(deps.as_ref(), mock_env()),
+            vec![
+                Record {
+                    key: "address".to_string(),
+                    value: "alice_wallet".to_string(),
+                },
+                Record {
+                    key: "contenthash".to_string(),
+                    value: "ipfs://bafy".to_string(),
+                },
+                Record {
+                    key: "url".to_string(),
+                    value: "https://alice.example".to_string(),
+                },
+            ]
+        );
+
+        // the name resolves to the address record instead of the owner
+        assert_name_owner(deps.as_ref(), "alice", "alice_wallet");
+
+        // an empty value removes the record
+        set_record(deps.as_mut(), "alice_key", "address", "").unwrap();
+        assert_name_owner(deps.as_ref(), "alice", "alice_key");
+        assert_eq!(query_records(deps.as_ref(), mock_env()).len(), 2);
+
+        // expired names have no records
+        assert!(query_records(deps.as_ref(), env_after(31_536_000)).is_empty());
+
+        // records do not survive a transfer
+        let info = mock_info("alice_key", &[]);
+        let msg = ExecuteMsg::Transfer {
+            name: "alice".to_string(),
+            to: "bob_key".to_string(),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg)
+            .expect("contract successfully handles Transfer message");
+        assert!(query_records(deps.as_ref(), mock_env()).is_empty());
+    }
+
+    #[test]
+    fn fails_on_invalid_records() {
+        let mut deps = mock_dependencies();
+        mock_init_no_price(deps.as_mut());
+        mock_alice_registers_name(deps.as_mut(), &[]);
+
+        match set_record(deps.as_mut(), "alice_key", "", "value") {
+            Ok(_) => panic!("Must return error"),
+            Err(ContractError::EmptyRecordKey {}) => {}
+            Err(e) => panic!("Unexpected error: {:?}", e),
+        }
+        match set_record(deps.as_mut(), "alice_key", &"k".repeat(65), "value") {
+            Ok(_) => panic!("Must return error"),
+            Err(ContractError::RecordKeyTooLong { length, .. }) => assert_eq!(length, 65),
+            Err(e) => panic!("Unexpected error: {:?}", e),
+        }
+        match set_record(deps.as_mut(), "alice_key", "Avatar", "value") {
+            Ok(_) => panic!("Must return error"),
+            Err(ContractError::InvalidCharacter { c }) => assert_eq!(c, 'A'),
+            Err(e) => panic!("Unexpected error: {:?}", e),
+        }
+        match set_record(deps.as_mut(), "alice_key", "description", &"v".repeat(1025)) {
+            Ok(_) => panic!("Must return error"),
+            Err(ContractError::RecordValueTooLong { length, .. }) => assert_eq!(length, 1025),
+            Err(e) => panic!("Unexpected error: {:?}", e),
+        }
+
+        for i in 0..32 {
+            set_record(deps.as_mut(), "alice_key", &format!("text.{}", i), "value").unwrap();
+        }
+        match set_record(deps.as_mut(), "alice_key", "text.32", "value") {
+            Ok(_) => panic!("Must return error"),
+            Err(ContractError::TooManyRecords { max_records }) => assert_eq!(max_records, 32),
+            Err(e) => panic!("Unexpected error: {:?}", e),
+        }
+        // existing records can still be updated
+        set_record(deps.as_mut(), "alice_key", "text.0", "other").unwrap();
+    }
 }
@@ -1,10 +1,22 @@
 use cosmwasm_std::{
-    entry_point, to_binary, BankMsg, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdResult,
+    attr, entry_point, from_binary, to_binary, Addr, Attribute, BankMsg, Binary, BlockInfo, Coin,
+    CosmosMsg, Decimal, Deps, DepsMut, Env, MessageInfo, Order, Reply, Response, StdResult,
+    Storage, SubMsg, Uint128, WasmMsg,
 };
+use cw20::{Cw20Coin, Cw20CoinVerified, Cw20Contract, Cw20ExecuteMsg, Cw20ReceiveMsg};
+use cw_storage_plus::Bound;
+use cw_utils::{parse_reply_instantiate_data, Expiration};
+use std::cmp::Ordering;
 
 use crate::error::ContractError;
-use crate::msg::{ConfigResponse, ExecuteMsg, InstantiateMsg, QueryMsg};
-use crate::state::{State, CONFIG};
+use crate::msg::{
+    ConfigResponse, ExecuteMsg, InstantiateMsg, ListingResponse, OptionInfo, OptionsResponse,
+    OracleQueryMsg, PriceResponse, QueryMsg, ReceiveMsg, StatusResponse, TokenInstantiateMsg,
+};
+use crate::state::{
+    expires_key, options, CashSettlement, ExerciseStyle, OptionStatus, OptionType, State, ACTIVE,
+    CONFIG, OPTION_SEQ,
+};
 
 #[entry_point]
 pub fn instantiate(
@@ -13,23 +25,109 @@ pub fn instantiate(
     info: MessageInfo,
     msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
-    if msg.expires <= env.block.height {
+    if msg.expires.is_expired(&env.block) {
         return Err(ContractError::OptionExpired {
             expired: msg.expires,
         });
     }
 
+    let counter_offer = normalize_coins(msg.counter_offer);
+    let cw20_counter_offer = match msg.cw20_counter_offer {
+        Some(offer) => {
+            if !counter_offer.is_empty() {
+                return Err(ContractError::MixedCounterOffer {});
+            }
+            Some(Cw20CoinVerified {
+                address: deps.api.addr_validate(&offer.address)?,
+                amount: offer.amount,
+            })
+        }
+        None => None,
+    };
+
+    let collateral = normalize_coins(info.funds);
+    let cash_settlement = match msg.cash_settlement {
+        Some(cash) => {
+            if !counter_offer.is_empty() || cw20_counter_offer.is_some() {
+                return Err(ContractError::CashSettledCounterOffer {});
+            }
+            if collateral.len() != 1 {
+                return Err(ContractError::CashSettledCollateral {});
+            }
+            Some(CashSettlement {
+                oracle: deps.api.addr_validate(&cash.oracle)?,
+                strike: cash.strike,
+            })
+        }
+        None => None,
+    };
+
     let state = State {
         creator: info.sender.clone(),
         owner: info.sender.clone(),
-        collateral: info.funds,
-        counter_offer: msg.counter_offer,
+        collateral,
+        cw20_collateral: vec![],
+        cw20_whitelist: validate_whitelist(deps.as_ref(), msg.cw20_whitelist)?,
+        counter_offer,
+        cw20_counter_offer,
         expires: msg.expires,
+        option_type: msg.option_type.unwrap_or(OptionType::Call),
+        exercise_style: validate_exercise_style(msg.exercise_style, &msg.expires)?,
+        size: validate_size(msg.size)?,
+        token: None,
+        price: None,
+        cash_settlement,
+        cancel_proposal: None,
+        status: OptionStatus::Active,
     };
 
     CONFIG.save(deps.storage, &state)?;
 
-    Ok(Response::default())
+    let mut res = Response::new()
+        .add_attribute("action", "instantiate")
+        .add_attributes(option_attributes(None, &state));
+    if let Some(token) = msg.token {
+        // all units go to the creator, the token address is stored on reply
+        let instantiate = WasmMsg::Instantiate {
+            admin: None,
+            code_id: token.code_id,
+            msg: to_binary(&TokenInstantiateMsg {
+                name: token.name,
+                symbol: token.symbol.clone(),
+                decimals: token.decimals,
+                initial_balances: vec![Cw20Coin {
+                    address: state.creator.to_string(),
+                    amount: state.size,
+                }],
+                mint: None,
+            })?,
+            funds: vec![],
+            label: format!("{} option token", token.symbol),
+        };
+        res = res.add_submessage(SubMsg::reply_on_success(
+            instantiate,
+            INSTANTIATE_TOKEN_REPLY_ID,
+        ));
+    }
+    Ok(res)
+}
+
+const INSTANTIATE_TOKEN_REPLY_ID: u64 = 1;
+
+#[entry_point]
+pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractError> {
+    match msg.id {
+        INSTANTIATE_TOKEN_REPLY_ID => {
+            let res = parse_reply_instantiate_data(msg)?;
+            let token = deps.api.addr_validate(&res.contract_address)?;
+            CONFIG.update(deps.storage, |mut state| -> StdResult<_> {
+                state.token = Some(token.clone());
+                Ok(state)
+            })?;
+            Ok(Response::new().add_attribute("token", token))
+        }
+        id => Err(ContractError::UnknownReplyId { id }),
+    }
 }
 
 #[entry_point]
@@ -40,216 +138,1062 @@ pub fn execute(
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
     match msg {
-        ExecuteMsg::Transfer { recipient } => execute_transfer(deps, env, info, recipient),
-        ExecuteMsg::Execute {} => execute_execute(deps, env, info),
-        ExecuteMsg::Burn {} => execute_burn(deps, env, info),
+        ExecuteMsg::Transfer { id, recipient } => execute_transfer(deps, env, info, id, recipient),
+        ExecuteMsg::Execute { id, size } => execute_execute(deps, env, info, id, size),
+        ExecuteMsg::Burn { id } => execute_burn(deps, env, info, id),
+        ExecuteMsg::Receive(msg) => execute_receive(deps, env, info, msg),
+        ExecuteMsg::Write {
+            counter_offer,
+            expires,
+            size,
+            option_type,
+            exercise_style,
+            cw20_whitelist,
+        } => execute_write(
+            deps,
+            env,
+            info,
+            counter_offer,
+            expires,
+            size,
+            option_type,
+            exercise_style,
+            cw20_whitelist,
+        ),
+        ExecuteMsg::ListForSale { id, price } => execute_list_for_sale(deps, env, info, id, price),
+        ExecuteMsg::CancelListing { id } => execute_cancel_listing(deps, env, info, id),
+        ExecuteMsg::Buy { id } => execute_buy(deps, env, info, id),
+        ExecuteMsg::AddCollateral { id } => execute_add_collateral(deps, env, info, id),
+        ExecuteMsg::ProposeCancel { id, expires } => {
+            execute_propose_cancel(deps, env, info, id, expires)
+        }
+        ExecuteMsg::AcceptCancel { id } => execute_accept_cancel(deps, env, info, id),
+    }
+}
+
+fn validate_whitelist(deps: Deps, whitelist: Option<Vec<String>>) -> StdResult<Vec<Addr>> {
+    whitelist
+        .unwrap_or_default()
+        .iter()
+        .map(|addr| deps.api.addr_validate(addr))
+        .collect()
+}
+
+fn validate_size(size: Option<Uint128>) -> Result<Uint128, ContractError> {
+    let size = size.unwrap_or_else(Uint128::one);
+    if size.is_zero() {
+        return Err(ContractError::InvalidSize {});
+    }
+    Ok(size)
+}
+
+fn validate_exercise_style(
+    style: Option<ExerciseStyle>,
+    expires: &Expiration,
+) -> Result<ExerciseStyle, ContractError> {
+    let style = style.unwrap_or(ExerciseStyle::American);
+    if let ExerciseStyle::European { start } = style {
+        // also fails if start and expires are not both heights or both times
+        if start.partial_cmp(expires) != Some(Ordering::Less) {
+            return Err(ContractError::InvalidExerciseWindow {
+                start,
+                expires: *expires,
+            });
+        }
+    }
+    Ok(style)
+}
+
+// an option can be exercised until it expires, from the start of its window if European
+fn ensure_exercisable(state: &State, block: &BlockInfo) -> Result<(), ContractError> {
+    if state.expires.is_expired(block) {
+        return Err(ContractError::OptionExpired {
+            expired: state.expires,
+        });
+    }
+    if let ExerciseStyle::European { start } = state.exercise_style {
+        if !start.is_expired(block) {
+            return Err(ContractError::ExerciseWindowNotOpen { start });
+        }
+    }
+    Ok(())
+}
+
+// the option with the given id, or the one created at instantiation if None
+fn load_option(storage: &dyn Storage, id: Option<u64>) -> StdResult<State> {
+    match id {
+        Some(id) => options().load(storage, id),
+        None => CONFIG.load(storage),
+    }
+}
+
+fn save_option(storage: &mut dyn Storage, id: Option<u64>, state: &State) -> StdResult<()> {
+    match id {
+        Some(id) => options().save(storage, id, state),
+        None => CONFIG.save(storage, state),
+    }
+}
+
+// the option to act on, which must not be exercised or burned yet
+fn load_active_option(storage: &dyn Storage, id: Option<u64>) -> Result<State, ContractError> {
+    let state = load_option(storage, id)?;
+    if state.status != OptionStatus::Active {
+        return Err(ContractError::OptionClosed {
+            status: state.status,
+        });
+    }
+    Ok(state)
+}
+
+// attributes describing the option for indexers, added to every action. The option
+// created at instantiation has option_id 0.
+fn option_attributes(id: Option<u64>, state: &State) -> Vec<Attribute> {
+    vec![
+        attr("option_id", id.unwrap_or_default().to_string()),
+        attr("owner", &state.owner),
+        attr(
+            "collateral",
+            format_amounts(&state.collateral, &state.cw20_collateral),
+        ),
+        attr(
+            "counter_offer",
+            format_amounts(
+                &state.counter_offer,
+                &state.cw20_counter_offer.iter().cloned().collect::<Vec<_>>(),
+            ),
+        ),
+    ]
+}
+
+// comma separated amounts, cw20 tokens denominated by their address
+fn format_amounts(native: &[Coin], cw20: &[Cw20CoinVerified]) -> String {
+    native
+        .iter()
+        .map(|c| c.to_string())
+        .chain(cw20.iter().map(|c| format!("{}{}", c.amount, c.address)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn execute_write(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    counter_offer: Vec<Coin>,
+    expires: Expiration,
+    size: Option<Uint128>,
+    option_type: Option<OptionType>,
+    exercise_style: Option<ExerciseStyle>,
+    cw20_whitelist: Option<Vec<String>>,
+) -> Result<Response, ContractError> {
+    if expires.is_expired(&env.block) {
+        return Err(ContractError::OptionExpired { expired: expires });
     }
+    let cw20_whitelist = validate_whitelist(deps.as_ref(), cw20_whitelist)?;
+
+    let id = OPTION_SEQ.may_load(deps.storage)?.unwrap_or_default() + 1;
+    OPTION_SEQ.save(deps.storage, &id)?;
+
+    let state = State {
+        creator: info.sender.clone(),
+        owner: info.sender,
+        collateral: normalize_coins(info.funds),
+        cw20_collateral: vec![],
+        cw20_whitelist,
+        counter_offer: normalize_coins(counter_offer),
+        cw20_counter_offer: None,
+        expires,
+        option_type: option_type.unwrap_or(OptionType::Call),
+        exercise_style: validate_exercise_style(exercise_style, &expires)?,
+        size: validate_size(size)?,
+        token: None,
+        price: None,
+        cash_settlement: None,
+        cancel_proposal: None,
+        status: OptionStatus::Active,
+    };
+    options().save(deps.storage, id, &state)?;
+
+    let res = Response::new()
+        .add_attribute("action", "write")
+        .add_attributes(option_attributes(Some(id), &state));
+    Ok(res)
 }
 
 pub fn execute_transfer(
     deps: DepsMut,
     _env: Env,
     info: MessageInfo,
+    id: Option<u64>,
     recipient: String,
 ) -> Result<Response, ContractError> {
     // ensure msg sender is the owner
-    let mut state = CONFIG.load(deps.storage)?;
+    let mut state = load_active_option(deps.storage, id)?;
+    if state.token.is_some() {
+        return Err(ContractError::Tokenized {});
+    }
     if info.sender != state.owner {
         return Err(ContractError::Unauthorized {});
     }
 
-    // set new owner on state
+    // set new owner on state, the listing and cancel proposal were for the previous one
     state.owner = deps.api.addr_validate(&recipient)?;
-    CONFIG.save(deps.storage, &state)?;
+    state.price = None;
+    state.cancel_proposal = None;
+    save_option(deps.storage, id, &state)?;
 
-    let res =
-        Response::new().add_attributes([("action", "transfer"), ("owner", recipient.as_str())]);
+    let res = Response::new()
+        .add_attribute("action", "transfer")
+        .add_attributes(option_attributes(id, &state));
     Ok(res)
 }
 
-pub fn execute_execute(
+pub fn execute_list_for_sale(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
+    id: Option<u64>,
+    price: Vec<Coin>,
 ) -> Result<Response, ContractError> {
     // ensure msg sender is the owner
-    let state = CONFIG.load(deps.storage)?;
+    let mut state = load_active_option(deps.storage, id)?;
+    if state.token.is_some() {
+        return Err(ContractError::Tokenized {});
+    }
     if info.sender != state.owner {
         return Err(ContractError::Unauthorized {});
     }
 
     // ensure not expired
-    if env.block.height >= state.expires {
+    if state.expires.is_expired(&env.block) {
         return Err(ContractError::OptionExpired {
             expired: state.expires,
         });
     }
 
-    // ensure sending proper counter_offer
-    if info.funds != state.counter_offer {
-        return Err(ContractError::CounterOfferMismatch {
-            offer: info.funds,
-            counter_offer: state.counter_offer,
-        });
+    // stored like the counter offer, so Buy can compare it with the sent funds
+    let price = normalize_coins(price);
+    if price.is_empty() {
+        return Err(ContractError::EmptyPrice {});
     }
+    state.price = Some(price);
+    save_option(deps.storage, id, &state)?;
 
-    // release counter_offer to creator
-    let mut res = Response::new();
-    res = res.add_message(BankMsg::Send {
-        to_address: state.creator.to_string(),
-        amount: state.counter_offer,
-    });
+    let res = Response::new()
+        .add_attribute("action", "list_for_sale")
+        .add_attributes(option_attributes(id, &state));
+    Ok(res)
+}
 
-    // release collateral to sender
-    res = res.add_message(BankMsg::Send {
-        to_address: state.owner.to_string(),
-        amount: state.collateral,
-    });
+pub fn execute_cancel_listing(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    id: Option<u64>,
+) -> Result<Response, ContractError> {
+    // ensure msg sender is the owner
+    let mut state = load_active_option(deps.storage, id)?;
+    if info.sender != state.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    if state.price.is_none() {
+        return Err(ContractError::NotListed {});
+    }
 
-    // delete the option
-    CONFIG.remove(deps.storage);
+    state.price = None;
+    save_option(deps.storage, id, &state)?;
 
-    res = res.add_attribute("action", "execute");
+    let res = Response::new()
+        .add_attribute("action", "cancel_listing")
+        .add_attributes(option_attributes(id, &state));
     Ok(res)
 }
 
-pub fn execute_burn(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
-    // ensure is expired
-    let state = CONFIG.load(deps.storage)?;
-    if env.block.height < state.expires {
-        return Err(ContractError::OptionNotExpired {
-            expires: state.expires,
+pub fn execute_buy(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: Option<u64>,
+) -> Result<Response, ContractError> {
+    let mut state = load_active_option(deps.storage, id)?;
+    let price = state.price.take().ok_or(ContractError::NotListed {})?;
+
+    // ensure not expired
+    if state.expires.is_expired(&env.block) {
+        return Err(ContractError::OptionExpired {
+            expired: state.expires,
         });
     }
 
-    // ensure sending proper counter_offer
-    if !info.funds.is_empty() {
-        return Err(ContractError::FundsSentWithBurn {});
+    // ensure sending the asked premium
+    let paid = normalize_coins(info.funds);
+    if paid != price {
+        return Err(ContractError::PriceMismatch { paid, price });
     }
 
-    // release collateral to creator
-    let mut res = Response::new();
-    res = res.add_message(BankMsg::Send {
-        to_address: state.creator.to_string(),
-        amount: state.collateral,
-    });
-
-    // delete the option
-    CONFIG.remove(deps.storage);
+    // pay the premium to the seller and hand the option to the buyer
+    let seller = state.owner;
+    state.owner = info.sender;
+    state.cancel_proposal = None;
+    save_option(deps.storage, id, &state)?;
 
-    res = res.add_attribute("action", "burn");
+    let res = Response::new()
+        .add_message(BankMsg::Send {
+            to_address: seller.to_string(),
+            amount: price,
+        })
+        .add_attributes([("action", "buy"), ("seller", seller.as_str())])
+        .add_attributes(option_attributes(id, &state));
     Ok(res)
 }
 
-#[entry_point]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
-    match msg {
-        QueryMsg::Config {} => to_binary(&query_config(deps)?),
+pub fn execute_add_collateral(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: Option<u64>,
+) -> Result<Response, ContractError> {
+    // ensure msg sender is the creator
+    let mut state = load_active_option(deps.storage, id)?;
+    if info.sender != state.creator {
+        return Err(ContractError::Unauthorized {});
     }
-}
 
-fn query_config(deps: Deps) -> StdResult<ConfigResponse> {
-    let state = CONFIG.load(deps.storage)?;
-    Ok(state)
+    // ensure not expired
+    if state.expires.is_expired(&env.block) {
+        return Err(ContractError::OptionExpired {
+            expired: state.expires,
+        });
+    }
+
+    let collateral = normalize_coins([state.collateral, info.funds].concat());
+    if state.cash_settlement.is_some() && collateral.len() != 1 {
+        return Err(ContractError::CashSettledCollateral {});
+    }
+    state.collateral = collateral;
+    save_option(deps.storage, id, &state)?;
+
+    let res = Response::new()
+        .add_attribute("action", "add_collateral")
+        .add_attributes(option_attributes(id, &state));
+    Ok(res)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
-    use cosmwasm_std::{attr, coins, CosmosMsg};
+pub fn execute_propose_cancel(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: Option<u64>,
+    expires: Expiration,
+) -> Result<Response, ContractError> {
+    // ensure msg sender is the creator
+    let mut state = load_active_option(deps.storage, id)?;
+    if state.token.is_some() {
+        return Err(ContractError::Tokenized {});
+    }
+    if info.sender != state.creator {
+        return Err(ContractError::Unauthorized {});
+    }
 
-    #[test]
-    fn proper_initialization() {
-        let mut deps = mock_dependencies();
+    if expires.is_expired(&env.block) {
+        return Err(ContractError::CancelProposalExpired { expired: expires });
+    }
+    state.cancel_proposal = Some(expires);
+    save_option(deps.storage, id, &state)?;
 
-        let msg = InstantiateMsg {
-            counter_offer: coins(40, "ETH"),
-            expires: 100_000,
-        };
-        let info = mock_info("creator", &coins(1, "BTC"));
+    let res = Response::new()
+        .add_attribute("action", "propose_cancel")
+        .add_attributes(option_attributes(id, &state));
+    Ok(res)
+}
 
-        // we can just call .unwrap() to assert this was a success
-        let res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
-        assert_eq!(0, res.messages.len());
+pub fn execute_accept_cancel(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: Option<u64>,
+) -> Result<Response, ContractError> {
+    // ensure msg sender is the owner
+    let mut state = load_active_option(deps.storage, id)?;
+    if info.sender != state.owner {
+        return Err(ContractError::Unauthorized {});
+    }
 
-        // it worked, let's query the state
-        let res = query_config(deps.as_ref()).unwrap();
-        assert_eq!(100_000, res.expires);
-        assert_eq!("creator", res.owner.as_str());
-        assert_eq!("creator", res.creator.as_str());
-        assert_eq!(coins(1, "BTC"), res.collateral);
-        assert_eq!(coins(40, "ETH"), res.counter_offer);
+    // ensure the proposal still stands
+    let proposal = state
+        .cancel_proposal
+        .take()
+        .ok_or(ContractError::NoCancelProposal {})?;
+    if proposal.is_expired(&env.block) {
+        return Err(ContractError::CancelProposalExpired { expired: proposal });
     }
 
-    #[test]
-    fn transfer() {
-        let mut deps = mock_dependencies();
+    // return the collateral to the creator
+    let mut res = Response::new();
+    res = res.add_messages(transfer_msgs(
+        &state.creator,
+        std::mem::take(&mut state.collateral),
+        std::mem::take(&mut state.cw20_collateral),
+    )?);
 
-        let msg = InstantiateMsg {
-            counter_offer: coins(40, "ETH"),
-            expires: 100_000,
-        };
-        let info = mock_info("creator", &coins(1, "BTC"));
+    state.price = None;
+    state.status = OptionStatus::Cancelled;
+    save_option(deps.storage, id, &state)?;
 
-        // we can just call .unwrap() to assert this was a success
-        let res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
-        assert_eq!(0, res.messages.len());
+    res = res
+        .add_attribute("action", "accept_cancel")
+        .add_attributes(option_attributes(id, &state));
+    Ok(res)
+}
 
-        // random cannot transfer
-        let info = mock_info("anyone", &[]);
-        let err =
-            execute_transfer(deps.as_mut(), mock_env(), info, "anyone".to_string()).unwrap_err();
-        match err {
-            ContractError::Unauthorized {} => {}
-            e => panic!("unexpected error: {}", e),
+pub fn execute_execute(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: Option<u64>,
+    size: Option<Uint128>,
+) -> Result<Response, ContractError> {
+    // ensure msg sender is the owner, or any holder of a tokenized option
+    let state = load_active_option(deps.storage, id)?;
+    if state.token.is_none() && info.sender != state.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    // ensure within the exercise window
+    ensure_exercisable(&state, &env.block)?;
+
+    // ensure sending proper counter_offer
+    let size = exercise_size(&state, size)?;
+    let (counter_offer, cw20_counter_offer) = counter_offer_part(&state, size);
+    if cw20_counter_offer.is_some() {
+        return Err(ContractError::Cw20CounterOfferMismatch {
+            offer: None,
+            counter_offer: cw20_counter_offer,
+        });
+    }
+    let offer = normalize_coins(info.funds);
+    let refund = match overpayment(&offer, &counter_offer) {
+        Some(refund) => refund,
+        None => {
+            return Err(ContractError::CounterOfferMismatch {
+                offer,
+                counter_offer,
+            })
         }
+    };
 
-        // owner can transfer
-        let info = mock_info("creator", &[]);
-        let res = execute_transfer(deps.as_mut(), mock_env(), info, "someone".to_string()).unwrap();
-        assert_eq!(res.attributes.len(), 2);
-        assert_eq!(res.attributes[0], attr("action", "transfer"));
+    // anything sent beyond the counter offer goes back to the sender
+    let res = settle(deps, id, state, size, &info.sender)?;
+    Ok(res.add_messages(transfer_msgs(&info.sender, refund, vec![])?))
+}
 
-        // check updated properly
-        let res = query_config(deps.as_ref()).unwrap();
-        assert_eq!("someone", res.owner.as_str());
-        assert_eq!("creator", res.creator.as_str());
+// sorted by denom, with duplicate denoms merged and zero amounts removed
+fn normalize_coins(coins: Vec<Coin>) -> Vec<Coin> {
+    let mut normalized: Vec<Coin> = vec![];
+    for coin in coins {
+        match normalized.iter_mut().find(|c| c.denom == coin.denom) {
+            Some(existing) => existing.amount += coin.amount,
+            None => normalized.push(coin),
+        }
     }
+    normalized.retain(|c| !c.amount.is_zero());
+    normalized.sort_by(|a, b| a.denom.cmp(&b.denom));
+    normalized
+}
 
-    #[test]
-    fn execute() {
-        let mut deps = mock_dependencies();
+// what is paid beyond the counter offer, or None if the counter offer is not covered.
+// Both must be normalized.
+fn overpayment(paid: &[Coin], counter_offer: &[Coin]) -> Option<Vec<Coin>> {
+    let mut rest = paid.to_vec();
+    for required in counter_offer {
+        let coin = rest.iter_mut().find(|c| c.denom == required.denom)?;
+        coin.amount = coin.amount.checked_sub(required.amount).ok()?;
+    }
+    rest.retain(|c| !c.amount.is_zero());
+    Some(rest)
+}
 
-        let amount = coins(40, "ETH");
-        let collateral = coins(1, "BTC");
-        let expires = 100_000;
-        let msg = InstantiateMsg {
-            counter_offer: amount.clone(),
-            expires,
-        };
-        let info = mock_info("creator", &collateral);
+pub fn execute_receive(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    wrapped: Cw20ReceiveMsg,
+) -> Result<Response, ContractError> {
+    let msg: ReceiveMsg = from_binary(&wrapped.msg)?;
+    let sender = deps.api.addr_validate(&wrapped.sender)?;
+    // the cw20 contract calling us is the token that was sent
+    let token = Cw20CoinVerified {
+        address: info.sender,
+        amount: wrapped.amount,
+    };
+    match msg {
+        ReceiveMsg::Collateral { id } => receive_collateral(deps, env, id, sender, token),
+        ReceiveMsg::Execute { id, size } => receive_execute(deps, env, id, size, sender, token),
+    }
+}
 
-        // we can just call .unwrap() to assert this was a success
-        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+pub fn receive_collateral(
+    deps: DepsMut,
+    env: Env,
+    id: Option<u64>,
+    sender: Addr,
+    token: Cw20CoinVerified,
+) -> Result<Response, ContractError> {
+    // ensure msg sender is the creator
+    let mut state = load_active_option(deps.storage, id)?;
+    if sender != state.creator {
+        return Err(ContractError::Unauthorized {});
+    }
+    if state.cash_settlement.is_some() {
+        return Err(ContractError::CashSettledCollateral {});
+    }
+    // only whitelisted cw20 contracts, anyone could call Receive otherwise
+    if !state.cw20_whitelist.contains(&token.address) {
+        return Err(ContractError::NotInWhitelist {
+            token: token.address.into(),
+        });
+    }
 
-        // set new owner
-        let info = mock_info("creator", &[]);
-        let _ = execute_transfer(deps.as_mut(), mock_env(), info, "owner".to_string()).unwrap();
+    // ensure not expired
+    if state.expires.is_expired(&env.block) {
+        return Err(ContractError::OptionExpired {
+            expired: state.expires,
+        });
+    }
 
-        // random cannot execute
-        let info = mock_info("creator", &amount);
-        let err = execute_execute(deps.as_mut(), mock_env(), info).unwrap_err();
-        match err {
-            ContractError::Unauthorized {} => {}
-            e => panic!("unexpected error: {}", e),
-        }
+    match state
+        .cw20_collateral
+        .iter_mut()
+        .find(|c| c.address == token.address)
+    {
+        Some(existing) => existing.amount += token.amount,
+        None => state.cw20_collateral.push(token.clone()),
+    }
+    save_option(deps.storage, id, &state)?;
 
-        // expired cannot execute
-        let info = mock_info("owner", &amount);
-        let mut env = mock_env();
-        env.block.height = 200_000;
-        let err = execute_execute(deps.as_mut(), env, info).unwrap_err();
+    let res = Response::new()
+        .add_attributes([
+            ("action", "add_collateral"),
+            ("token", token.address.as_str()),
+            ("amount", &token.amount.to_string()),
+        ])
+        .add_attributes(option_attributes(id, &state));
+    Ok(res)
+}
+
+pub fn receive_execute(
+    deps: DepsMut,
+    env: Env,
+    id: Option<u64>,
+    size: Option<Uint128>,
+    sender: Addr,
+    token: Cw20CoinVerified,
+) -> Result<Response, ContractError> {
+    // ensure msg sender is the owner, or any holder of a tokenized option
+    let state = load_active_option(deps.storage, id)?;
+    if state.token.is_none() && sender != state.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    // ensure within the exercise window
+    ensure_exercisable(&state, &env.block)?;
+
+    // ensure sending proper cw20 counter_offer
+    let size = exercise_size(&state, size)?;
+    let (_, cw20_counter_offer) = counter_offer_part(&state, size);
+    if cw20_counter_offer.as_ref() != Some(&token) {
+        return Err(ContractError::Cw20CounterOfferMismatch {
+            offer: Some(token),
+            counter_offer: cw20_counter_offer,
+        });
+    }
+
+    settle(deps, id, state, size, &sender)
+}
+
+fn exercise_size(state: &State, size: Option<Uint128>) -> Result<Uint128, ContractError> {
+    let size = size.unwrap_or(state.size);
+    if size.is_zero() {
+        return Err(ContractError::InvalidSize {});
+    }
+    if size > state.size {
+        return Err(ContractError::ExerciseTooLarge {
+            size,
+            remaining: state.size,
+        });
+    }
+    Ok(size)
+}
+
+// the fraction size / total of amount, rounded up or down
+fn part(amount: Uint128, size: Uint128, total: Uint128, round_up: bool) -> Uint128 {
+    let part = amount.multiply_ratio(size, total);
+    if round_up && amount.full_mul(size) > part.full_mul(total) {
+        return part + Uint128::one();
+    }
+    part
+}
+
+// the counter offer to pay for size, rounded up so the owner never pays less than
+// the proportional amount
+fn counter_offer_part(state: &State, size: Uint128) -> (Vec<Coin>, Option<Cw20CoinVerified>) {
+    let counter_offer = state
+        .counter_offer
+        .iter()
+        .map(|c| Coin::new(part(c.amount, size, state.size, true).u128(), &c.denom))
+        .collect();
+    let cw20_counter_offer = state.cw20_counter_offer.as_ref().map(|c| Cw20CoinVerified {
+        address: c.address.clone(),
+        amount: part(c.amount, size, state.size, true),
+    });
+    (counter_offer, cw20_counter_offer)
+}
+
+// the collateral released for size, rounded down
+fn collateral_part(state: &State, size: Uint128) -> (Vec<Coin>, Vec<Cw20CoinVerified>) {
+    let collateral = state
+        .collateral
+        .iter()
+        .map(|c| Coin::new(part(c.amount, size, state.size, false).u128(), &c.denom))
+        .filter(|c| !c.amount.is_zero())
+        .collect();
+    let cw20_collateral = state
+        .cw20_collateral
+        .iter()
+        .map(|c| Cw20CoinVerified {
+            address: c.address.clone(),
+            amount: part(c.amount, size, state.size, false),
+        })
+        .filter(|c| !c.amount.is_zero())
+        .collect();
+    (collateral, cw20_collateral)
+}
+
+// releases the counter offer to the creator and the collateral to the exerciser for size,
+// keeping the rest of the option open. Tokens of a tokenized option are burnt first.
+fn settle(
+    deps: DepsMut,
+    id: Option<u64>,
+    mut state: State,
+    size: Uint128,
+    exerciser: &Addr,
+) -> Result<Response, ContractError> {
+    let (counter_offer, cw20_counter_offer) = counter_offer_part(&state, size);
+    let (collateral, cw20_collateral) = collateral_part(&state, size);
+
+    let mut res = Response::new();
+    if let Some(token) = &state.token {
+        res = res.add_message(Cw20Contract(token.clone()).call(Cw20ExecuteMsg::BurnFrom {
+            owner: exerciser.into(),
+            amount: size,
+        })?);
+    }
+    res = res.add_messages(transfer_msgs(
+        &state.creator,
+        counter_offer.clone(),
+        cw20_counter_offer.clone().into_iter().collect(),
+    )?);
+    match &state.cash_settlement {
+        Some(cash) => {
+            let price = query_price(deps.as_ref(), &cash.oracle)?;
+            let (payout, rest) = cash_payout(cash, &state.option_type, price, size, &collateral);
+            res = res
+                .add_messages(transfer_msgs(exerciser, payout, vec![])?)
+                .add_messages(transfer_msgs(&state.creator, rest, vec![])?)
+                .add_attribute("settlement_price", price.to_string());
+        }
+        None => {
+            res = res.add_messages(transfer_msgs(
+                exerciser,
+                collateral.clone(),
+                cw20_collateral.clone(),
+            )?);
+        }
+    }
+
+    // the listing was for the full remaining size
+    state.price = None;
+    state.size -= size;
+    subtract_coins(&mut state.counter_offer, &counter_offer);
+    subtract_coins(&mut state.collateral, &collateral);
+    if let (Some(remaining), Some(paid)) = (&mut state.cw20_counter_offer, cw20_counter_offer) {
+        remaining.amount -= paid.amount;
+    }
+    for released in cw20_collateral {
+        if let Some(c) = state
+            .cw20_collateral
+            .iter_mut()
+            .find(|c| c.address == released.address)
+        {
+            c.amount -= released.amount;
+        }
+    }
+    state.cw20_collateral.retain(|c| !c.amount.is_zero());
+    if state.size.is_zero() {
+        state.status = OptionStatus::Exercised;
+    }
+    save_option(deps.storage, id, &state)?;
+
+    res = res
+        .add_attributes([("action", "execute"), ("size", &size.to_string())])
+        .add_attributes(option_attributes(id, &state));
+    Ok(res)
+}
+
+fn query_price(deps: Deps, oracle: &Addr) -> StdResult<Decimal> {
+    let res: PriceResponse = deps
+        .querier
+        .query_wasm_smart(oracle, &OracleQueryMsg::Price {})?;
+    Ok(res.price)
+}
+
+// splits the collateral released for size into the in-the-money amount paid to the
+// exerciser, capped by the collateral, and the rest returned to the creator
+fn cash_payout(
+    cash: &CashSettlement,
+    option_type: &OptionType,
+    price: Decimal,
+    size: Uint128,
+    collateral: &[Coin],
+) -> (Vec<Coin>, Vec<Coin>) {
+    let intrinsic = match option_type {
+        OptionType::Call if price > cash.strike => price - cash.strike,
+        OptionType::Put if cash.strike > price => cash.strike - price,
+        _ => Decimal::zero(),
+    };
+    let mut payout = vec![];
+    let mut rest = vec![];
+    for c in collateral {
+        let paid = (size * intrinsic).min(c.amount);
+        payout.push(Coin::new(paid.u128(), &c.denom));
+        rest.push(Coin::new((c.amount - paid).u128(), &c.denom));
+    }
+    payout.retain(|c| !c.amount.is_zero());
+    rest.retain(|c| !c.amount.is_zero());
+    (payout, rest)
+}
+
+// parts are never larger than the amounts they are taken from
+fn subtract_coins(amounts: &mut Vec<Coin>, parts: &[Coin]) {
+    for part in parts {
+        if let Some(c) = amounts.iter_mut().find(|c| c.denom == part.denom) {
+            c.amount -= part.amount;
+        }
+    }
+    amounts.retain(|c| !c.amount.is_zero());
+}
+
+pub fn execute_burn(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: Option<u64>,
+) -> Result<Response, ContractError> {
+    // ensure is expired
+    let mut state = load_active_option(deps.storage, id)?;
+    if !state.expires.is_expired(&env.block) {
+        return Err(ContractError::OptionNotExpired {
+            expires: state.expires,
+        });
+    }
+
+    // ensure sending proper counter_offer
+    if !info.funds.is_empty() {
+        return Err(ContractError::FundsSentWithBurn {});
+    }
+
+    // release collateral to creator
+    let mut res = Response::new();
+    res = res.add_messages(transfer_msgs(
+        &state.creator,
+        std::mem::take(&mut state.collateral),
+        std::mem::take(&mut state.cw20_collateral),
+    )?);
+
+    // keep the burned option for its history
+    state.price = None;
+    state.status = OptionStatus::Burned;
+    save_option(deps.storage, id, &state)?;
+
+    res = res
+        .add_attribute("action", "burn")
+        .add_attributes(option_attributes(id, &state));
+    Ok(res)
+}
+
+fn transfer_msgs(
+    to_address: &Addr,
+    amount: Vec<Coin>,
+    cw20_amount: Vec<Cw20CoinVerified>,
+) -> StdResult<Vec<CosmosMsg>> {
+    let mut msgs = vec![];
+    if !amount.is_empty() {
+        msgs.push(
+            BankMsg::Send {
+                to_address: to_address.into(),
+                amount,
+            }
+            .into(),
+        );
+    }
+    for coin in cw20_amount {
+        msgs.push(Cw20Contract(coin.address).call(Cw20ExecuteMsg::Transfer {
+            recipient: to_address.into(),
+            amount: coin.amount,
+        })?);
+    }
+    Ok(msgs)
+}
+
+#[entry_point]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::Config { id } => to_binary(&query_config(deps, id)?),
+        QueryMsg::Status { id } => to_binary(&query_status(deps, env, id)?),
+        QueryMsg::Listing { id } => to_binary(&query_listing(deps, id)?),
+        QueryMsg::ListByOwner {
+            owner,
+            start_after,
+            limit,
+        } => to_binary(&query_list_by_owner(deps, owner, start_after, limit)?),
+        QueryMsg::ListByCreator {
+            creator,
+            start_after,
+            limit,
+        } => to_binary(&query_list_by_creator(deps, creator, start_after, limit)?),
+        QueryMsg::ListByExpiry { start_after, limit } => {
+            to_binary(&query_list_by_expiry(deps, start_after, limit)?)
+        }
+    }
+}
+
+fn query_config(deps: Deps, id: Option<u64>) -> StdResult<ConfigResponse> {
+    let state = load_option(deps.storage, id)?;
+    Ok(state)
+}
+
+fn query_status(deps: Deps, env: Env, id: Option<u64>) -> StdResult<StatusResponse> {
+    let state = load_option(deps.storage, id)?;
+    let status = match state.status {
+        OptionStatus::Active if state.expires.is_expired(&env.block) => OptionStatus::Expired,
+        status => status,
+    };
+    Ok(StatusResponse { status })
+}
+
+fn query_listing(deps: Deps, id: Option<u64>) -> StdResult<ListingResponse> {
+    let state = load_option(deps.storage, id)?;
+    Ok(ListingResponse {
+        owner: state.owner,
+        price: state.price,
+    })
+}
+
+// Limits for pagination
+const MAX_LIMIT: u32 = 30;
+const DEFAULT_LIMIT: u32 = 10;
+
+fn options_response(options: StdResult<Vec<(u64, State)>>) -> StdResult<OptionsResponse> {
+    Ok(OptionsResponse {
+        options: options?
+            .into_iter()
+            .map(|(id, option)| OptionInfo { id, option })
+            .collect(),
+    })
+}
+
+fn query_list_by_owner(
+    deps: Deps,
+    owner: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<OptionsResponse> {
+    let owner = deps.api.addr_validate(&owner)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+    let options = options()
+        .idx
+        .owner
+        .prefix((ACTIVE, owner))
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .collect();
+    options_response(options)
+}
+
+fn query_list_by_creator(
+    deps: Deps,
+    creator: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<OptionsResponse> {
+    let creator = deps.api.addr_validate(&creator)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+    let options = options()
+        .idx
+        .creator
+        .prefix((ACTIVE, creator))
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .collect();
+    options_response(options)
+}
+
+fn query_list_by_expiry(
+    deps: Deps,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<OptionsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    // options are ordered by (expires, id), so continue after the given option
+    let start = match start_after {
+        Some(id) => {
+            let option = options().load(deps.storage, id)?;
+            Some(Bound::exclusive((expires_key(&option.expires), id)))
+        }
+        None => None,
+    };
+    let options = options()
+        .idx
+        .expires
+        .sub_prefix(ACTIVE)
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .collect();
+    options_response(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::msg::{CashSettlementMsg, TokenMsg};
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cosmwasm_std::{
+        attr, coin, coins, ContractResult, CosmosMsg, SubMsgResponse, SubMsgResult, SystemResult,
+        Uint128, WasmMsg, WasmQuery,
+    };
+    use std::str::FromStr;
+
+    #[test]
+    fn proper_initialization() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            counter_offer: coins(40, "ETH"),
+            cw20_counter_offer: None,
+            expires: Expiration::AtHeight(100_000),
+            size: None,
+            token: None,
+            option_type: None,
+            exercise_style: None,
+            cash_settlement: None,
+            cw20_whitelist: None,
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+
+        // we can just call .unwrap() to assert this was a success
+        let res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        // it worked, let's query the state
+        let res = query_config(deps.as_ref(), None).unwrap();
+        assert_eq!(Expiration::AtHeight(100_000), res.expires);
+        assert_eq!("creator", res.owner.as_str());
+        assert_eq!("creator", res.creator.as_str());
+        assert_eq!(coins(1, "BTC"), res.collateral);
+        assert_eq!(coins(40, "ETH"), res.counter_offer);
+    }
+
+    #[test]
+    fn transfer() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            counter_offer: coins(40, "ETH"),
+            cw20_counter_offer: None,
+            expires: Expiration::AtHeight(100_000),
+            size: None,
+            token: None,
+            option_type: None,
+            exercise_style: None,
+            cash_settlement: None,
+            cw20_whitelist: None,
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+
+        // we can just call .unwrap() to assert this was a success
+        let res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        // random cannot transfer
+        let info = mock_info("anyone", &[]);
+        let err = execute_transfer(deps.as_mut(), mock_env(), info, None, "anyone".to_string())
+            .unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // owner can transfer
+        let info = mock_info("creator", &[]);
+        let res =
+            execute_transfer(deps.as_mut(), mock_env(), info, None, "someone".to_string()).unwrap();
+        assert_eq!(res.attributes.len(), 5);
+        assert_eq!(res.attributes[0], attr("action", "transfer"));
+        assert_eq!(res.attributes[1], attr("option_id", "0"));
+        assert_eq!(res.attributes[2], attr("owner", "someone"));
+        assert_eq!(res.attributes[3], attr("collateral", "1BTC"));
+        assert_eq!(res.attributes[4], attr("counter_offer", "40ETH"));
+
+        // check updated properly
+        let res = query_config(deps.as_ref(), None).unwrap();
+        assert_eq!("someone", res.owner.as_str());
+        assert_eq!("creator", res.creator.as_str());
+    }
+
+    #[test]
+    fn execute() {
+        let mut deps = mock_dependencies();
+
+        let amount = coins(40, "ETH");
+        let collateral = coins(1, "BTC");
+        let expires = Expiration::AtHeight(100_000);
+        let msg = InstantiateMsg {
+            counter_offer: amount.clone(),
+            cw20_counter_offer: None,
+            expires,
+            size: None,
+            token: None,
+            option_type: None,
+            exercise_style: None,
+            cash_settlement: None,
+            cw20_whitelist: None,
+        };
+        let info = mock_info("creator", &collateral);
+
+        // we can just call .unwrap() to assert this was a success
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // set new owner
+        let info = mock_info("creator", &[]);
+        let _ =
+            execute_transfer(deps.as_mut(), mock_env(), info, None, "owner".to_string()).unwrap();
+
+        // random cannot execute
+        let info = mock_info("creator", &amount);
+        let err = execute_execute(deps.as_mut(), mock_env(), info, None, None).unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // expired cannot execute
+        let info = mock_info("owner", &amount);
+        let mut env = mock_env();
+        env.block.height = 200_000;
+        let err = execute_execute(deps.as_mut(), env, info, None, None).unwrap_err();
         match err {
             ContractError::OptionExpired { expired } => assert_eq!(expired, expires),
             e => panic!("unexpected error: {}", e),
@@ -258,7 +1202,7 @@ mod tests {
         // bad counter_offer cannot execute
         let msg_offer = coins(39, "ETH");
         let info = mock_info("owner", &msg_offer);
-        let err = execute_execute(deps.as_mut(), mock_env(), info).unwrap_err();
+        let err = execute_execute(deps.as_mut(), mock_env(), info, None, None).unwrap_err();
         match err {
             ContractError::CounterOfferMismatch {
                 offer,
@@ -272,7 +1216,7 @@ mod tests {
 
         // proper execution
         let info = mock_info("owner", &amount);
-        let res = execute_execute(deps.as_mut(), mock_env(), info).unwrap();
+        let res = execute_execute(deps.as_mut(), mock_env(), info, None, None).unwrap();
         assert_eq!(res.messages.len(), 2);
         assert_eq!(
             res.messages[0].msg,
@@ -289,8 +1233,15 @@ mod tests {
             })
         );
 
-        // check deleted
-        let _ = query_config(deps.as_ref()).unwrap_err();
+        // the option is kept as exercised and cannot be executed again
+        let res = query_status(deps.as_ref(), mock_env(), None).unwrap();
+        assert_eq!(res.status, OptionStatus::Exercised);
+        let info = mock_info("owner", &coins(40, "ETH"));
+        let err = execute_execute(deps.as_mut(), mock_env(), info, None, None).unwrap_err();
+        match err {
+            ContractError::OptionClosed { status } => assert_eq!(status, OptionStatus::Exercised),
+            e => panic!("unexpected error: {}", e),
+        }
     }
 
     #[test]
@@ -299,10 +1250,17 @@ mod tests {
 
         let counter_offer = coins(40, "ETH");
         let collateral = coins(1, "BTC");
-        let msg_expires = 100_000;
+        let msg_expires = Expiration::AtHeight(100_000);
         let msg = InstantiateMsg {
             counter_offer: counter_offer.clone(),
+            cw20_counter_offer: None,
             expires: msg_expires,
+            size: None,
+            token: None,
+            option_type: None,
+            exercise_style: None,
+            cash_settlement: None,
+            cw20_whitelist: None,
         };
         let info = mock_info("creator", &collateral);
 
@@ -311,11 +1269,12 @@ mod tests {
 
         // set new owner
         let info = mock_info("creator", &[]);
-        let _ = execute_transfer(deps.as_mut(), mock_env(), info, "owner".to_string()).unwrap();
+        let _ =
+            execute_transfer(deps.as_mut(), mock_env(), info, None, "owner".to_string()).unwrap();
 
         // non-expired cannot execute
         let info = mock_info("anyone", &[]);
-        let err = execute_burn(deps.as_mut(), mock_env(), info).unwrap_err();
+        let err = execute_burn(deps.as_mut(), mock_env(), info, None).unwrap_err();
         match err {
             ContractError::OptionNotExpired { expires } => assert_eq!(expires, msg_expires),
             e => panic!("unexpected error: {}", e),
@@ -325,7 +1284,7 @@ mod tests {
         let info = mock_info("anyone", &counter_offer);
         let mut env = mock_env();
         env.block.height = 200_000;
-        let err = execute_burn(deps.as_mut(), env, info).unwrap_err();
+        let err = execute_burn(deps.as_mut(), env, info, None).unwrap_err();
         match err {
             ContractError::FundsSentWithBurn {} => {}
             e => panic!("unexpected error: {}", e),
@@ -335,7 +1294,9 @@ mod tests {
         let info = mock_info("anyone", &[]);
         let mut env = mock_env();
         env.block.height = 200_000;
-        let res = execute_burn(deps.as_mut(), env, info).unwrap();
+        let res = query_status(deps.as_ref(), env.clone(), None).unwrap();
+        assert_eq!(res.status, OptionStatus::Expired);
+        let res = execute_burn(deps.as_mut(), env, info, None).unwrap();
         assert_eq!(res.messages.len(), 1);
         assert_eq!(
             res.messages[0].msg,
@@ -345,7 +1306,964 @@ mod tests {
             })
         );
 
-        // check deleted
-        let _ = query_config(deps.as_ref()).unwrap_err();
+        // the option is kept as burned
+        let res = query_status(deps.as_ref(), mock_env(), None).unwrap();
+        assert_eq!(res.status, OptionStatus::Burned);
+    }
+
+    fn cw20_receive(sender: &str, amount: u128, msg: ReceiveMsg) -> Cw20ReceiveMsg {
+        Cw20ReceiveMsg {
+            sender: sender.to_string(),
+            amount: Uint128::new(amount),
+            msg: to_binary(&msg).unwrap(),
+        }
+    }
+
+    fn cw20_transfer(token: &str, recipient: &str, amount: u128) -> CosmosMsg {
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: token.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: recipient.to_string(),
+                amount: Uint128::new(amount),
+            })
+            .unwrap(),
+            funds: vec![],
+        })
+    }
+
+    #[test]
+    fn cw20_collateral_and_counter_offer() {
+        let mut deps = mock_dependencies();
+
+        let counter_offer = Cw20Coin {
+            address: "usdc".to_string(),
+            amount: Uint128::new(40),
+        };
+
+        // counter offer cannot mix native and cw20 tokens
+        let msg = InstantiateMsg {
+            counter_offer: coins(40, "ETH"),
+            cw20_counter_offer: Some(counter_offer.clone()),
+            expires: Expiration::AtHeight(100_000),
+            size: None,
+            token: None,
+            option_type: None,
+            exercise_style: None,
+            cash_settlement: None,
+            cw20_whitelist: None,
+        };
+        let info = mock_info("creator", &[]);
+        let err = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        match err {
+            ContractError::MixedCounterOffer {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        let msg = InstantiateMsg {
+            counter_offer: vec![],
+            cw20_counter_offer: Some(counter_offer),
+            expires: Expiration::AtHeight(100_000),
+            size: None,
+            token: None,
+            option_type: None,
+            exercise_style: None,
+            cash_settlement: None,
+            cw20_whitelist: Some(vec!["wbtc".to_string()]),
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // only whitelisted tokens are accepted as collateral
+        let info = mock_info("fake", &[]);
+        let msg = cw20_receive("creator", 5, ReceiveMsg::Collateral { id: None });
+        let err = execute_receive(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        match err {
+            ContractError::NotInWhitelist { token } => assert_eq!(token, "fake"),
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // only the creator adds collateral
+        let info = mock_info("wbtc", &[]);
+        let msg = cw20_receive("anyone", 5, ReceiveMsg::Collateral { id: None });
+        let err = execute_receive(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        for amount in [3, 2] {
+            let info = mock_info("wbtc", &[]);
+            let msg = cw20_receive("creator", amount, ReceiveMsg::Collateral { id: None });
+            let _ = execute_receive(deps.as_mut(), mock_env(), info, msg).unwrap();
+        }
+        let res = query_config(deps.as_ref(), None).unwrap();
+        assert_eq!(
+            res.cw20_collateral,
+            vec![Cw20CoinVerified {
+                address: Addr::unchecked("wbtc"),
+                amount: Uint128::new(5),
+            }]
+        );
+
+        let info = mock_info("creator", &[]);
+        let _ =
+            execute_transfer(deps.as_mut(), mock_env(), info, None, "owner".to_string()).unwrap();
+
+        // native counter offer is not accepted
+        let info = mock_info("owner", &[]);
+        let err = execute_execute(deps.as_mut(), mock_env(), info, None, None).unwrap_err();
+        match err {
+            ContractError::Cw20CounterOfferMismatch { offer, .. } => assert_eq!(offer, None),
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // wrong token or amount cannot execute
+        for (token, amount) in [("usdt", 40), ("usdc", 39)] {
+            let info = mock_info(token, &[]);
+            let msg = cw20_receive(
+                "owner",
+                amount,
+                ReceiveMsg::Execute {
+                    id: None,
+                    size: None,
+                },
+            );
+            let err = execute_receive(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+            match err {
+                ContractError::Cw20CounterOfferMismatch { .. } => {}
+                e => panic!("unexpected error: {}", e),
+            }
+        }
+
+        // only the owner can execute
+        let info = mock_info("usdc", &[]);
+        let msg = cw20_receive(
+            "creator",
+            40,
+            ReceiveMsg::Execute {
+                id: None,
+                size: None,
+            },
+        );
+        let err = execute_receive(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // proper execution
+        let info = mock_info("usdc", &[]);
+        let msg = cw20_receive(
+            "owner",
+            40,
+            ReceiveMsg::Execute {
+                id: None,
+                size: None,
+            },
+        );
+        let res = execute_receive(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(res.messages.len(), 3);
+        assert_eq!(res.messages[0].msg, cw20_transfer("usdc", "creator", 40));
+        assert_eq!(
+            res.messages[1].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "owner".into(),
+                amount: coins(1, "BTC"),
+            })
+        );
+        assert_eq!(res.messages[2].msg, cw20_transfer("wbtc", "owner", 5));
+
+        // the option is kept as exercised and cannot be executed again
+        let res = query_status(deps.as_ref(), mock_env(), None).unwrap();
+        assert_eq!(res.status, OptionStatus::Exercised);
+    }
+
+    #[test]
+    fn burn_cw20_collateral() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            counter_offer: coins(40, "ETH"),
+            cw20_counter_offer: None,
+            expires: Expiration::AtHeight(100_000),
+            size: None,
+            token: None,
+            option_type: None,
+            exercise_style: None,
+            cash_settlement: None,
+            cw20_whitelist: Some(vec!["wbtc".to_string()]),
+        };
+        let info = mock_info("creator", &[]);
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let info = mock_info("wbtc", &[]);
+        let msg = cw20_receive("creator", 5, ReceiveMsg::Collateral { id: None });
+        let _ = execute_receive(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // expired returns the cw20 collateral
+        let info = mock_info("anyone", &[]);
+        let mut env = mock_env();
+        env.block.height = 200_000;
+        let res = execute_burn(deps.as_mut(), env, info, None).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(res.messages[0].msg, cw20_transfer("wbtc", "creator", 5));
+    }
+
+    #[test]
+    fn write_and_list() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            counter_offer: vec![],
+            cw20_counter_offer: None,
+            expires: Expiration::AtHeight(100_000),
+            size: None,
+            token: None,
+            option_type: None,
+            exercise_style: None,
+            cash_settlement: None,
+            cw20_whitelist: None,
+        };
+        let info = mock_info("maker", &[]);
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // cannot write an expired option
+        let info = mock_info("maker", &coins(1, "BTC"));
+        let err = execute_write(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            coins(40, "ETH"),
+            Expiration::AtHeight(10),
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap_err();
+        match err {
+            ContractError::OptionExpired { expired } => {
+                assert_eq!(expired, Expiration::AtHeight(10))
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+
+        for (creator, expires) in [("maker", 300_000), ("maker", 100_000), ("other", 200_000)] {
+            let info = mock_info(creator, &coins(1, "BTC"));
+            let _ = execute_write(
+                deps.as_mut(),
+                mock_env(),
+                info,
+                coins(40, "ETH"),
+                Expiration::AtHeight(expires),
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        }
+        let res = query_config(deps.as_ref(), Some(2)).unwrap();
+        assert_eq!(Expiration::AtHeight(100_000), res.expires);
+        assert_eq!("maker", res.owner.as_str());
+        assert_eq!(coins(1, "BTC"), res.collateral);
+
+        // options are transferred one by one
+        let info = mock_info("maker", &[]);
+        let _ = execute_transfer(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            Some(1),
+            "buyer".to_string(),
+        )
+        .unwrap();
+        let info = mock_info("maker", &[]);
+        let err = execute_transfer(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            Some(3),
+            "buyer".to_string(),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        let ids = |res: OptionsResponse| res.options.into_iter().map(|o| o.id).collect::<Vec<_>>();
+        let res = query_list_by_owner(deps.as_ref(), "buyer".to_string(), None, None).unwrap();
+        assert_eq!(ids(res), vec![1]);
+        let res = query_list_by_creator(deps.as_ref(), "maker".to_string(), None, None).unwrap();
+        assert_eq!(ids(res), vec![1, 2]);
+        let res = query_list_by_creator(deps.as_ref(), "maker".to_string(), Some(1), None).unwrap();
+        assert_eq!(ids(res), vec![2]);
+        let res = query_list_by_expiry(deps.as_ref(), None, None).unwrap();
+        assert_eq!(ids(res), vec![2, 3, 1]);
+        let res = query_list_by_expiry(deps.as_ref(), Some(2), Some(1)).unwrap();
+        assert_eq!(ids(res), vec![3]);
+
+        // the buyer executes their option only
+        let info = mock_info("buyer", &coins(40, "ETH"));
+        let res = execute_execute(deps.as_mut(), mock_env(), info, Some(1), None).unwrap();
+        assert_eq!(
+            res.messages[1].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "buyer".into(),
+                amount: coins(1, "BTC"),
+            })
+        );
+        let res = query_status(deps.as_ref(), mock_env(), Some(1)).unwrap();
+        assert_eq!(res.status, OptionStatus::Exercised);
+        let res = query_list_by_owner(deps.as_ref(), "buyer".to_string(), None, None).unwrap();
+        assert!(res.options.is_empty());
+        let res = query_list_by_expiry(deps.as_ref(), None, None).unwrap();
+        assert_eq!(ids(res), vec![2, 3]);
+
+        // expired option is burned back to its creator, the others are untouched
+        let info = mock_info("anyone", &[]);
+        let mut env = mock_env();
+        env.block.height = 150_000;
+        let res = execute_burn(deps.as_mut(), env, info, Some(2)).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "maker".into(),
+                amount: coins(1, "BTC"),
+            })
+        );
+        let res = query_list_by_expiry(deps.as_ref(), None, None).unwrap();
+        assert_eq!(ids(res), vec![3]);
+        let _ = query_config(deps.as_ref(), None).unwrap();
+
+        // both options of the maker are closed and left the active part of the indexes,
+        // so listing never scans them
+        let active: Vec<u64> = options()
+            .idx
+            .creator
+            .prefix((ACTIVE, Addr::unchecked("maker")))
+            .keys(&deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()
+            .unwrap();
+        assert!(active.is_empty());
+    }
+
+    #[test]
+    fn partial_execute() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            counter_offer: coins(100, "ETH"),
+            cw20_counter_offer: None,
+            expires: Expiration::AtHeight(100_000),
+            size: Some(Uint128::zero()),
+            token: None,
+            option_type: None,
+            exercise_style: None,
+            cash_settlement: None,
+            cw20_whitelist: None,
+        };
+        let info = mock_info("creator", &coins(10, "BTC"));
+        let err = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+        match err {
+            ContractError::InvalidSize {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        let msg = InstantiateMsg {
+            counter_offer: coins(100, "ETH"),
+            cw20_counter_offer: None,
+            expires: Expiration::AtHeight(100_000),
+            size: Some(Uint128::new(3)),
+            token: None,
+            option_type: None,
+            exercise_style: None,
+            cash_settlement: None,
+            cw20_whitelist: None,
+        };
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // cannot exercise more than the size
+        let info = mock_info("creator", &coins(100, "ETH"));
+        let err = execute_execute(deps.as_mut(), mock_env(), info, None, Some(Uint128::new(4)))
+            .unwrap_err();
+        match err {
+            ContractError::ExerciseTooLarge { size, remaining } => {
+                assert_eq!(size, Uint128::new(4));
+                assert_eq!(remaining, Uint128::new(3));
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // a third of the option costs a third of the counter offer, rounded up
+        let info = mock_info("creator", &coins(33, "ETH"));
+        let err = execute_execute(deps.as_mut(), mock_env(), info, None, Some(Uint128::one()))
+            .unwrap_err();
+        match err {
+            ContractError::CounterOfferMismatch { counter_offer, .. } => {
+                assert_eq!(counter_offer, coins(34, "ETH"))
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+        let info = mock_info("creator", &coins(34, "ETH"));
+        let res =
+            execute_execute(deps.as_mut(), mock_env(), info, None, Some(Uint128::one())).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(34, "ETH"),
+            })
+        );
+        // and releases a third of the collateral, rounded down
+        assert_eq!(
+            res.messages[1].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(3, "BTC"),
+            })
+        );
+
+        let res = query_config(deps.as_ref(), None).unwrap();
+        assert_eq!(res.size, Uint128::new(2));
+        assert_eq!(res.counter_offer, coins(66, "ETH"));
+        assert_eq!(res.collateral, coins(7, "BTC"));
+
+        let info = mock_info("creator", &coins(33, "ETH"));
+        let _ =
+            execute_execute(deps.as_mut(), mock_env(), info, None, Some(Uint128::one())).unwrap();
+
+        // burn only returns the remainder
+        let info = mock_info("anyone", &[]);
+        let mut env = mock_env();
+        env.block.height = 200_000;
+        let res = execute_burn(deps.as_mut(), env, info, None).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(4, "BTC"),
+            })
+        );
+    }
+
+    #[test]
+    fn tokenized_option() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            counter_offer: coins(100, "ETH"),
+            cw20_counter_offer: None,
+            expires: Expiration::AtHeight(100_000),
+            size: Some(Uint128::new(10)),
+            token: Some(TokenMsg {
+                code_id: 7,
+                name: "BTC call".to_string(),
+                symbol: "BTCC".to_string(),
+                decimals: 0,
+            }),
+            option_type: None,
+            exercise_style: None,
+            cash_settlement: None,
+            cw20_whitelist: None,
+        };
+        let info = mock_info("creator", &coins(10, "BTC"));
+        let res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(res.messages[0].id, INSTANTIATE_TOKEN_REPLY_ID);
+        match &res.messages[0].msg {
+            CosmosMsg::Wasm(WasmMsg::Instantiate { code_id, msg, .. }) => {
+                assert_eq!(*code_id, 7);
+                let msg: TokenInstantiateMsg = from_binary(msg).unwrap();
+                assert_eq!(
+                    msg.initial_balances,
+                    vec![Cw20Coin {
+                        address: "creator".to_string(),
+                        amount: Uint128::new(10),
+                    }]
+                );
+            }
+            m => panic!("unexpected message: {:?}", m),
+        }
+
+        // the token address is stored on reply
+        let mut data = vec![0x0a, 5];
+        data.extend_from_slice(b"token");
+        let reply_msg = Reply {
+            id: INSTANTIATE_TOKEN_REPLY_ID,
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: vec![],
+                data: Some(Binary(data)),
+            }),
+        };
+        let _ = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+        let res = query_config(deps.as_ref(), None).unwrap();
+        assert_eq!(res.token, Some(Addr::unchecked("token")));
+
+        // ownership moves with the tokens
+        let info = mock_info("creator", &[]);
+        let err = execute_transfer(deps.as_mut(), mock_env(), info, None, "trader".to_string())
+            .unwrap_err();
+        match err {
+            ContractError::Tokenized {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // any holder exercises by burning tokens
+        let info = mock_info("trader", &coins(30, "ETH"));
+        let res =
+            execute_execute(deps.as_mut(), mock_env(), info, None, Some(Uint128::new(3))).unwrap();
+        assert_eq!(res.messages.len(), 3);
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "token".into(),
+                msg: to_binary(&Cw20ExecuteMsg::BurnFrom {
+                    owner: "trader".into(),
+                    amount: Uint128::new(3),
+                })
+                .unwrap(),
+                funds: vec![],
+            })
+        );
+        assert_eq!(
+            res.messages[2].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "trader".into(),
+                amount: coins(3, "BTC"),
+            })
+        );
+        let res = query_config(deps.as_ref(), None).unwrap();
+        assert_eq!(res.size, Uint128::new(7));
+    }
+
+    #[test]
+    fn european_put() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let start = Expiration::AtTime(env.block.time.plus_seconds(100));
+        let expires = Expiration::AtTime(env.block.time.plus_seconds(200));
+
+        // the window must start before expiry, in the same unit
+        for (start, expires) in [
+            (expires, start),
+            (Expiration::AtHeight(env.block.height + 1), expires),
+        ] {
+            let msg = InstantiateMsg {
+                counter_offer: coins(1, "BTC"),
+                cw20_counter_offer: None,
+                expires,
+                size: None,
+                token: None,
+                option_type: Some(OptionType::Put),
+                exercise_style: Some(ExerciseStyle::European { start }),
+                cash_settlement: None,
+                cw20_whitelist: None,
+            };
+            let info = mock_info("creator", &coins(40, "ETH"));
+            let err = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap_err();
+            match err {
+                ContractError::InvalidExerciseWindow { .. } => {}
+                e => panic!("unexpected error: {}", e),
+            }
+        }
+
+        let msg = InstantiateMsg {
+            counter_offer: coins(1, "BTC"),
+            cw20_counter_offer: None,
+            expires,
+            size: None,
+            token: None,
+            option_type: Some(OptionType::Put),
+            exercise_style: Some(ExerciseStyle::European { start }),
+            cash_settlement: None,
+            cw20_whitelist: None,
+        };
+        let info = mock_info("creator", &coins(40, "ETH"));
+        let _ = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let res = query_config(deps.as_ref(), None).unwrap();
+        assert_eq!(res.option_type, OptionType::Put);
+
+        // cannot exercise before the window opens
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let err =
+            execute_execute(deps.as_mut(), env.clone(), info.clone(), None, None).unwrap_err();
+        match err {
+            ContractError::ExerciseWindowNotOpen { start: s } => assert_eq!(s, start),
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // nor after expiry
+        let mut late = env.clone();
+        late.block.time = env.block.time.plus_seconds(200);
+        let err = execute_execute(deps.as_mut(), late, info.clone(), None, None).unwrap_err();
+        match err {
+            ContractError::OptionExpired { expired } => assert_eq!(expired, expires),
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // selling the BTC for the ETH collateral within the window
+        let mut within = env.clone();
+        within.block.time = env.block.time.plus_seconds(150);
+        let res = execute_execute(deps.as_mut(), within, info, None, None).unwrap();
+        assert_eq!(
+            res.messages[1].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(40, "ETH"),
+            })
+        );
+    }
+
+    #[test]
+    fn list_and_buy() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            counter_offer: coins(40, "ETH"),
+            cw20_counter_offer: None,
+            expires: Expiration::AtHeight(100_000),
+            size: None,
+            token: None,
+            option_type: None,
+            exercise_style: None,
+            cash_settlement: None,
+            cw20_whitelist: None,
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // nothing to buy yet
+        let info = mock_info("buyer", &coins(2, "ETH"));
+        let err = execute_buy(deps.as_mut(), mock_env(), info, None).unwrap_err();
+        match err {
+            ContractError::NotListed {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // only the owner lists
+        let info = mock_info("anyone", &[]);
+        let err = execute_list_for_sale(deps.as_mut(), mock_env(), info, None, coins(2, "ETH"))
+            .unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        let info = mock_info("creator", &[]);
+        let _ =
+            execute_list_for_sale(deps.as_mut(), mock_env(), info, None, coins(2, "ETH")).unwrap();
+        let res = query_listing(deps.as_ref(), None).unwrap();
+        assert_eq!(res.price, Some(coins(2, "ETH")));
+
+        // the premium must be paid in full
+        let info = mock_info("buyer", &coins(1, "ETH"));
+        let err = execute_buy(deps.as_mut(), mock_env(), info, None).unwrap_err();
+        match err {
+            ContractError::PriceMismatch { paid, price } => {
+                assert_eq!(paid, coins(1, "ETH"));
+                assert_eq!(price, coins(2, "ETH"));
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+
+        let info = mock_info("buyer", &coins(2, "ETH"));
+        let res = execute_buy(deps.as_mut(), mock_env(), info, None).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(2, "ETH"),
+            })
+        );
+        let res = query_listing(deps.as_ref(), None).unwrap();
+        assert_eq!(res.owner, Addr::unchecked("buyer"));
+        assert_eq!(res.price, None);
+
+        // a cancelled listing cannot be bought
+        let info = mock_info("buyer", &[]);
+        let _ = execute_list_for_sale(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            None,
+            coins(3, "ETH"),
+        )
+        .unwrap();
+        let _ = execute_cancel_listing(deps.as_mut(), mock_env(), info, None).unwrap();
+        let info = mock_info("other", &coins(3, "ETH"));
+        let err = execute_buy(deps.as_mut(), mock_env(), info, None).unwrap_err();
+        match err {
+            ContractError::NotListed {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn normalized_listing_price() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            counter_offer: coins(40, "ETH"),
+            cw20_counter_offer: None,
+            expires: Expiration::AtHeight(100_000),
+            size: None,
+            token: None,
+            option_type: None,
+            exercise_style: None,
+            cash_settlement: None,
+            cw20_whitelist: None,
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // a price of only zero amounts is empty
+        let info = mock_info("creator", &[]);
+        let err = execute_list_for_sale(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            None,
+            coins(0, "ETH"),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::EmptyPrice {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // the price is stored sorted and merged
+        let price = vec![
+            coin(1, "ETH"),
+            coin(0, "ATOM"),
+            coin(2, "DAI"),
+            coin(1, "ETH"),
+        ];
+        let _ = execute_list_for_sale(deps.as_mut(), mock_env(), info, None, price).unwrap();
+        let res = query_listing(deps.as_ref(), None).unwrap();
+        assert_eq!(res.price, Some(vec![coin(2, "DAI"), coin(2, "ETH")]));
+
+        // and the funds are compared in the same form
+        let info = mock_info("buyer", &[coin(2, "ETH"), coin(0, "ATOM"), coin(2, "DAI")]);
+        let res = execute_buy(deps.as_mut(), mock_env(), info, None).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: vec![coin(2, "DAI"), coin(2, "ETH")],
+            })
+        );
+        let res = query_listing(deps.as_ref(), None).unwrap();
+        assert_eq!(res.owner, Addr::unchecked("buyer"));
+    }
+
+    #[test]
+    fn tolerant_counter_offer() {
+        let mut deps = mock_dependencies();
+
+        // the counter offer is stored sorted and merged, without zero amounts
+        let msg = InstantiateMsg {
+            counter_offer: vec![
+                coin(30, "ETH"),
+                coin(0, "ATOM"),
+                coin(5, "DAI"),
+                coin(10, "ETH"),
+            ],
+            cw20_counter_offer: None,
+            expires: Expiration::AtHeight(100_000),
+            size: None,
+            token: None,
+            option_type: None,
+            exercise_style: None,
+            cash_settlement: None,
+            cw20_whitelist: None,
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let res = query_config(deps.as_ref(), None).unwrap();
+        assert_eq!(res.counter_offer, vec![coin(5, "DAI"), coin(40, "ETH")]);
+
+        // a missing coin still fails
+        let info = mock_info("creator", &coins(40, "ETH"));
+        let err = execute_execute(deps.as_mut(), mock_env(), info, None, None).unwrap_err();
+        match err {
+            ContractError::CounterOfferMismatch { .. } => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // order does not matter and overpayment is refunded
+        let info = mock_info(
+            "creator",
+            &[coin(45, "ETH"), coin(5, "DAI"), coin(3, "ATOM")],
+        );
+        let res = execute_execute(deps.as_mut(), mock_env(), info, None, None).unwrap();
+        assert_eq!(res.messages.len(), 3);
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: vec![coin(5, "DAI"), coin(40, "ETH")],
+            })
+        );
+        assert_eq!(
+            res.messages[2].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: vec![coin(3, "ATOM"), coin(5, "ETH")],
+            })
+        );
+    }
+
+    #[test]
+    fn cash_settled() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            counter_offer: coins(1, "BTC"),
+            cw20_counter_offer: None,
+            expires: Expiration::AtHeight(100_000),
+            size: Some(Uint128::new(10)),
+            token: None,
+            option_type: None,
+            exercise_style: None,
+            cash_settlement: Some(CashSettlementMsg {
+                oracle: "oracle".to_string(),
+                strike: Decimal::from_str("50").unwrap(),
+            }),
+            cw20_whitelist: None,
+        };
+        let info = mock_info("creator", &coins(1000, "USD"));
+        let err = instantiate(deps.as_mut(), mock_env(), info.clone(), msg.clone()).unwrap_err();
+        match err {
+            ContractError::CashSettledCounterOffer {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        let msg = InstantiateMsg {
+            counter_offer: vec![],
+            ..msg
+        };
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let oracle = |price: &'static str| {
+            move |query: &WasmQuery| match query {
+                WasmQuery::Smart { contract_addr, msg } if contract_addr == "oracle" => {
+                    assert_eq!(msg, &to_binary(&OracleQueryMsg::Price {}).unwrap());
+                    let res = PriceResponse {
+                        price: Decimal::from_str(price).unwrap(),
+                    };
+                    SystemResult::Ok(ContractResult::Ok(to_binary(&res).unwrap()))
+                }
+                _ => panic!("unexpected query: {:?}", query),
+            }
+        };
+
+        // the owner gets the in-the-money amount, the creator the rest
+        deps.querier.update_wasm(oracle("120"));
+        let info = mock_info("creator", &[]);
+        let res =
+            execute_execute(deps.as_mut(), mock_env(), info, None, Some(Uint128::new(3))).unwrap();
+        assert_eq!(res.messages.len(), 2);
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(210, "USD"),
+            })
+        );
+        assert_eq!(
+            res.messages[1].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(90, "USD"),
+            })
+        );
+
+        // the payout is capped by the collateral
+        deps.querier.update_wasm(oracle("200"));
+        let info = mock_info("creator", &[]);
+        let res = execute_execute(deps.as_mut(), mock_env(), info, None, None).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(700, "USD"),
+            })
+        );
+    }
+
+    #[test]
+    fn add_collateral_and_cancel() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            counter_offer: coins(40, "ETH"),
+            cw20_counter_offer: None,
+            expires: Expiration::AtHeight(100_000),
+            size: None,
+            token: None,
+            option_type: None,
+            exercise_style: None,
+            cash_settlement: None,
+            cw20_whitelist: None,
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let info = mock_info("creator", &[]);
+        let _ =
+            execute_transfer(deps.as_mut(), mock_env(), info, None, "owner".to_string()).unwrap();
+
+        // only the creator tops up the collateral
+        let info = mock_info("owner", &coins(1, "BTC"));
+        let err = execute_add_collateral(deps.as_mut(), mock_env(), info, None).unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        let info = mock_info("creator", &[coin(2, "BTC"), coin(5, "ATOM")]);
+        let _ = execute_add_collateral(deps.as_mut(), mock_env(), info, None).unwrap();
+        let res = query_config(deps.as_ref(), None).unwrap();
+        assert_eq!(res.collateral, vec![coin(5, "ATOM"), coin(3, "BTC")]);
+
+        // nothing to accept yet
+        let info = mock_info("owner", &[]);
+        let err = execute_accept_cancel(deps.as_mut(), mock_env(), info, None).unwrap_err();
+        match err {
+            ContractError::NoCancelProposal {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // a proposal can no longer be accepted once it expired
+        let info = mock_info("creator", &[]);
+        let _ = execute_propose_cancel(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            None,
+            Expiration::AtHeight(20_000),
+        )
+        .unwrap();
+        let info = mock_info("owner", &[]);
+        let mut env = mock_env();
+        env.block.height = 20_000;
+        let err = execute_accept_cancel(deps.as_mut(), env, info, None).unwrap_err();
+        match err {
+            ContractError::CancelProposalExpired { expired } => {
+                assert_eq!(expired, Expiration::AtHeight(20_000))
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // the owner accepts in time and the creator gets the collateral back
+        let info = mock_info("owner", &[]);
+        let res = execute_accept_cancel(deps.as_mut(), mock_env(), info, None).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: vec![coin(5, "ATOM"), coin(3, "BTC")],
+            })
+        );
+        let res = query_status(deps.as_ref(), mock_env(), None).unwrap();
+        assert_eq!(res.status, OptionStatus::Cancelled);
     }
 }
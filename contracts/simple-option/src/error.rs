@@ -1,4 +1,8 @@
-use cosmwasm_std::{Coin, StdError};
+use cosmwasm_std::{Coin, StdError, Uint128};
+use cw20::Cw20CoinVerified;
+use cw_utils::{Expiration, ParseReplyError};
+
+use crate::state::OptionStatus;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -6,16 +10,21 @@ pub enum ContractError {
     #[error("{0}")]
     Std(#[from] StdError),
 
+    #[error("{0}")]
+    ParseReply(#[from] ParseReplyError),
+
     #[error("expired option (expired {expired:?})")]
-    OptionExpired { expired: u64 },
+    OptionExpired { expired: Expiration },
 
     #[error("not expired option (expires {expires:?})")]
-    OptionNotExpired { expires: u64 },
+    OptionNotExpired { expires: Expiration },
 
     #[error("unauthorized")]
     Unauthorized {},
 
-    #[error("must send exact counter offer (offer {offer:?}, counter_offer: {counter_offer:?})")]
+    #[error(
+        "must send at least the counter offer (offer {offer:?}, counter_offer: {counter_offer:?})"
+    )]
     CounterOfferMismatch {
         offer: Vec<Coin>,
         counter_offer: Vec<Coin>,
@@ -23,4 +32,63 @@ pub enum ContractError {
 
     #[error("do not send funds with burn")]
     FundsSentWithBurn {},
+
+    #[error(
+        "must send exact cw20 counter offer (offer {offer:?}, counter_offer: {counter_offer:?})"
+    )]
+    Cw20CounterOfferMismatch {
+        offer: Option<Cw20CoinVerified>,
+        counter_offer: Option<Cw20CoinVerified>,
+    },
+
+    #[error("option size must be greater than zero")]
+    InvalidSize {},
+
+    #[error("cannot exercise more than the remaining size (size {size}, remaining {remaining})")]
+    ExerciseTooLarge { size: Uint128, remaining: Uint128 },
+
+    #[error("counter offer must be either native coins or a cw20 token")]
+    MixedCounterOffer {},
+
+    #[error("option is tokenized, transfer the option tokens instead")]
+    Tokenized {},
+
+    #[error("unknown reply id {id}")]
+    UnknownReplyId { id: u64 },
+
+    #[error("exercise window must start before expiry (start {start}, expires {expires})")]
+    InvalidExerciseWindow {
+        start: Expiration,
+        expires: Expiration,
+    },
+
+    #[error("exercise window not open yet (start {start})")]
+    ExerciseWindowNotOpen { start: Expiration },
+
+    #[error("option is not listed for sale")]
+    NotListed {},
+
+    #[error("listing price must not be empty")]
+    EmptyPrice {},
+
+    #[error("must send exact price (paid {paid:?}, price {price:?})")]
+    PriceMismatch { paid: Vec<Coin>, price: Vec<Coin> },
+
+    #[error("option is closed (status {status:?})")]
+    OptionClosed { status: OptionStatus },
+
+    #[error("cash-settled options take no counter offer")]
+    CashSettledCounterOffer {},
+
+    #[error("cash-settled options need a single native collateral coin")]
+    CashSettledCollateral {},
+
+    #[error("no cancel proposal")]
+    NoCancelProposal {},
+
+    #[error("cancel proposal expired (expired {expired})")]
+    CancelProposalExpired { expired: Expiration },
+
+    #[error("token is not in the cw20 whitelist (token {token})")]
+    NotInWhitelist { token: String },
 }
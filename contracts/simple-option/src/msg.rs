@@ -1,31 +1,189 @@
-use crate::state::State;
+use crate::state::{ExerciseStyle, OptionStatus, OptionType, State};
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::Coin;
+use cosmwasm_std::{Addr, Coin, Decimal, Uint128};
+use cw20::{Cw20Coin, Cw20ReceiveMsg, MinterResponse};
+use cw_utils::Expiration;
 
 #[cw_serde]
 pub struct InstantiateMsg {
     // owner and creator come from env
-    // collateral comes from env
+    // collateral comes from env, cw20 collateral is added with ReceiveMsg::Collateral
+    /// cw20 contracts whose tokens are accepted as collateral
+    pub cw20_whitelist: Option<Vec<String>>,
     pub counter_offer: Vec<Coin>,
-    pub expires: u64,
+    /// Counter offer paid in a cw20 token, counter_offer must be empty then
+    pub cw20_counter_offer: Option<Cw20Coin>,
+    pub expires: Expiration,
+    /// Defaults to a call. Only a label unless the option is cash-settled
+    pub option_type: Option<OptionType>,
+    /// Defaults to American, exercisable any time before expires
+    pub exercise_style: Option<ExerciseStyle>,
+    /// Divisible size of the option, defaults to 1. Exercising part of the size pays
+    /// and releases the same fraction of the counter offer and collateral.
+    pub size: Option<Uint128>,
+    /// Mints the option units as a cw20 token to the creator, making the option
+    /// tradable. Holders then exercise by burning tokens instead of the owner.
+    pub token: Option<TokenMsg>,
+    /// Settles the option in cash at the price of an oracle instead of delivering the
+    /// collateral. The collateral must be a single native coin and there is no counter
+    /// offer.
+    pub cash_settlement: Option<CashSettlementMsg>,
+}
+
+#[cw_serde]
+pub struct CashSettlementMsg {
+    pub oracle: String,
+    /// Price per unit of size, in the collateral denom
+    pub strike: Decimal,
+}
+
+/// Query answered by the price oracle of a cash-settled option
+#[cw_serde]
+pub enum OracleQueryMsg {
+    Price {},
+}
+
+#[cw_serde]
+pub struct PriceResponse {
+    /// Price of the underlying per unit of size, in the collateral denom
+    pub price: Decimal,
+}
+
+#[cw_serde]
+pub struct TokenMsg {
+    /// Code id of a cw20-base contract
+    pub code_id: u64,
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+/// InstantiateMsg of cw20-base, used to instantiate the option token
+#[cw_serde]
+pub struct TokenInstantiateMsg {
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+    pub initial_balances: Vec<Cw20Coin>,
+    pub mint: Option<MinterResponse>,
 }
 
 #[cw_serde]
 pub enum ExecuteMsg {
-    /// Owner can transfer to a new owner
-    Transfer { recipient: String },
-    /// Owner can post counter_offer on unexpired option to execute and get the collateral
-    Execute {},
+    /// Owner can transfer to a new owner. With an id, the written option is used,
+    /// otherwise the one created at instantiation. Tokenized options are transferred
+    /// as cw20 tokens instead.
+    Transfer { id: Option<u64>, recipient: String },
+    /// Owner can post counter_offer on unexpired option to execute and get the collateral.
+    /// Funds sent beyond the counter offer are refunded.
+    /// With a size, only that part of the option is exercised, paying and releasing
+    /// the same fraction of counter_offer and collateral. If the option is tokenized,
+    /// any holder can execute, burning size tokens it has allowed this contract to burn.
+    Execute {
+        id: Option<u64>,
+        size: Option<Uint128>,
+    },
     /// Burn will release collateral if expired
-    Burn {},
+    Burn { id: Option<u64> },
+    /// Write creates a new option with the sent funds as collateral. The sender is its
+    /// creator and owner.
+    Write {
+        counter_offer: Vec<Coin>,
+        expires: Expiration,
+        size: Option<Uint128>,
+        option_type: Option<OptionType>,
+        exercise_style: Option<ExerciseStyle>,
+        cw20_whitelist: Option<Vec<String>>,
+    },
+    /// Receive handles cw20 collateral deposits and executions paid in cw20 tokens
+    Receive(Cw20ReceiveMsg),
+    /// Owner lists the unexpired option for sale at price, replacing any earlier listing
+    ListForSale { id: Option<u64>, price: Vec<Coin> },
+    /// Owner withdraws the option from sale
+    CancelListing { id: Option<u64> },
+    /// Anyone buys a listed option by sending its price, which is paid to the owner
+    Buy { id: Option<u64> },
+    /// Creator adds the sent funds to the collateral of the unexpired option
+    AddCollateral { id: Option<u64> },
+    /// Creator proposes to cancel the option, replacing any earlier proposal. The owner
+    /// can accept it until expires.
+    ProposeCancel {
+        id: Option<u64>,
+        expires: Expiration,
+    },
+    /// Owner accepts the cancel proposal, returning the collateral to the creator
+    AcceptCancel { id: Option<u64> },
+}
+
+#[cw_serde]
+pub enum ReceiveMsg {
+    /// Creator adds the received cw20 tokens to the collateral. Only tokens in the
+    /// cw20 whitelist of the option are accepted.
+    Collateral { id: Option<u64> },
+    /// Owner posts the cw20 counter_offer on unexpired option to execute and get the
+    /// collateral, or the part of it matching size
+    Execute {
+        id: Option<u64>,
+        size: Option<Uint128>,
+    },
 }
 
 #[cw_serde]
 #[derive(QueryResponses)]
 pub enum QueryMsg {
+    /// Returns the written option with the given id, or the one created at instantiation
     #[returns(ConfigResponse)]
-    Config {},
+    Config { id: Option<u64> },
+    /// Returns whether the option is active, expired, exercised or burned
+    #[returns(StatusResponse)]
+    Status { id: Option<u64> },
+    /// Returns the owner of the option and its price if it is listed for sale
+    #[returns(ListingResponse)]
+    Listing { id: Option<u64> },
+    /// Lists the active written options held by owner
+    #[returns(OptionsResponse)]
+    ListByOwner {
+        owner: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Lists the active written options created by creator
+    #[returns(OptionsResponse)]
+    ListByCreator {
+        creator: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Lists the active written options by expiry, soonest first, options expiring at a height
+    /// before those expiring at a time. start_after is an option id.
+    #[returns(OptionsResponse)]
+    ListByExpiry {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
 }
 
 // We define a custom struct for each query response
 pub type ConfigResponse = State;
+
+#[cw_serde]
+pub struct OptionInfo {
+    pub id: u64,
+    pub option: State,
+}
+
+#[cw_serde]
+pub struct OptionsResponse {
+    pub options: Vec<OptionInfo>,
+}
+
+#[cw_serde]
+pub struct ListingResponse {
+    pub owner: Addr,
+    pub price: Option<Vec<Coin>>,
+}
+
+#[cw_serde]
+pub struct StatusResponse {
+    pub status: OptionStatus,
+}
@@ -1,16 +1,138 @@
 use cosmwasm_schema::cw_serde;
 
-use cosmwasm_std::{Addr, Coin};
-use cw_storage_plus::Item;
+use cosmwasm_std::{Addr, Coin, Decimal, Uint128};
+use cw20::Cw20CoinVerified;
+use cw_storage_plus::{Index, IndexList, IndexedMap, Item, MultiIndex};
+use cw_utils::Expiration;
+
+/// Metadata only for physically settled options, which swap the collateral for the
+/// counter offer either way. Cash-settled options use it to pick the payout direction.
+#[cw_serde]
+pub enum OptionType {
+    /// The collateral is the underlying, bought by paying the counter offer
+    Call,
+    /// The counter offer is the underlying, sold for the collateral
+    Put,
+}
+
+#[cw_serde]
+pub enum ExerciseStyle {
+    /// Exercisable any time before expiry
+    American,
+    /// Exercisable only from start until expiry
+    European { start: Expiration },
+}
+
+#[cw_serde]
+pub struct CashSettlement {
+    /// Price oracle answering `OracleQueryMsg::Price {}`
+    pub oracle: Addr,
+    /// Price per unit of size, in the collateral denom
+    pub strike: Decimal,
+}
+
+#[cw_serde]
+pub enum OptionStatus {
+    Active,
+    /// Past expiry and not burned yet, only reported by the Status query
+    Expired,
+    Exercised,
+    Burned,
+    /// Collateral returned to the creator early with the consent of the owner
+    Cancelled,
+}
 
 #[cw_serde]
 pub struct State {
     pub creator: Addr,
     pub owner: Addr,
     pub collateral: Vec<Coin>,
+    /// cw20 tokens deposited as collateral
+    pub cw20_collateral: Vec<Cw20CoinVerified>,
+    /// cw20 contracts accepted as collateral
+    pub cw20_whitelist: Vec<Addr>,
     pub counter_offer: Vec<Coin>,
-    pub expires: u64,
+    /// cw20 counter offer, replacing the native one
+    pub cw20_counter_offer: Option<Cw20CoinVerified>,
+    pub expires: Expiration,
+    pub option_type: OptionType,
+    pub exercise_style: ExerciseStyle,
+    /// Size left to exercise. collateral and counter_offer are the amounts for this size.
+    pub size: Uint128,
+    /// cw20 token minted for the option units. When set, holders exercise by burning
+    /// tokens instead of the owner.
+    pub token: Option<Addr>,
+    /// Premium asked by the owner while the option is listed for sale
+    pub price: Option<Vec<Coin>>,
+    /// When set, exercising pays the in-the-money amount out of the collateral
+    /// instead of swapping it for the counter offer
+    pub cash_settlement: Option<CashSettlement>,
+    /// Set while the creator proposes to cancel the option, the owner can accept until
+    /// this expiration
+    pub cancel_proposal: Option<Expiration>,
+    /// Active until the option is exercised in full or burned. Closed options are kept,
+    /// without collateral, so their history can be queried.
+    pub status: OptionStatus,
 }
 
 pub const CONFIG_KEY: &str = "config";
 pub const CONFIG: Item<State> = Item::new(CONFIG_KEY);
+
+/// Last id given to an option written with `Write`
+pub const OPTION_SEQ: Item<u64> = Item::new("option_seq");
+
+/// The index keys start with `ACTIVE` while the option is active, so listing only ranges
+/// over active options however many closed ones an address has
+pub struct OptionIndexes<'a> {
+    pub owner: MultiIndex<'a, (u8, Addr), State, u64>,
+    pub creator: MultiIndex<'a, (u8, Addr), State, u64>,
+    pub expires: MultiIndex<'a, (u8, (u8, u64)), State, u64>,
+}
+
+impl<'a> IndexList<State> for OptionIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<State>> + '_> {
+        let v: Vec<&dyn Index<State>> = vec![&self.owner, &self.creator, &self.expires];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Options written with `Write`, next to the option created at instantiation
+pub fn options<'a>() -> IndexedMap<'a, u64, State, OptionIndexes<'a>> {
+    let indexes = OptionIndexes {
+        owner: MultiIndex::new(
+            |o: &State| (status_key(o), o.owner.clone()),
+            "options",
+            "options__owner",
+        ),
+        creator: MultiIndex::new(
+            |o: &State| (status_key(o), o.creator.clone()),
+            "options",
+            "options__creator",
+        ),
+        expires: MultiIndex::new(
+            |o: &State| (status_key(o), expires_key(&o.expires)),
+            "options",
+            "options__expires",
+        ),
+    };
+    IndexedMap::new("options", indexes)
+}
+
+pub const ACTIVE: u8 = 1;
+const CLOSED: u8 = 0;
+
+fn status_key(option: &State) -> u8 {
+    match option.status {
+        OptionStatus::Active => ACTIVE,
+        _ => CLOSED,
+    }
+}
+
+/// Sort key of an expiration: heights first, then times, then never
+pub fn expires_key(expires: &Expiration) -> (u8, u64) {
+    match expires {
+        Expiration::AtHeight(height) => (0, *height),
+        Expiration::AtTime(time) => (1, time.nanos()),
+        Expiration::Never {} => (2, 0),
+    }
+}